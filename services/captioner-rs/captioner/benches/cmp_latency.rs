@@ -1,10 +1,14 @@
+#[cfg(feature = "turbo-ffi")]
 use bytes::Bytes;
+#[cfg(feature = "turbo-ffi")]
 use captioner::decode;
 use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
 use image::{self, DynamicImage};
+#[cfg(feature = "turbo-ffi")]
 use once_cell::sync::Lazy;
 use std::hint::black_box;
 use std::time::Duration;
+#[cfg(feature = "turbo-ffi")]
 use tokio::runtime::{Builder, Runtime};
 
 #[cfg(feature = "turbo")]
@@ -12,6 +16,7 @@ use turbojpeg;
 
 static JPEG: &[u8] = include_bytes!("../tests/fixtures/sample.jpg");
 
+#[cfg(feature = "turbo-ffi")]
 static RT: Lazy<Runtime> = Lazy::new(|| {
     Builder::new_current_thread()
         .enable_all()
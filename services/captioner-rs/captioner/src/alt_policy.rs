@@ -0,0 +1,294 @@
+// Alt text length policy. Lengths are counted in grapheme clusters (what a reader sees as
+// characters), and text is only ever cut on grapheme boundaries, preferring the end of a
+// clause, then the end of a word, and never leaving a dangling connector ("... with").
+//
+// Defaults come from CAPTIONER_ALT_MAX_LEN / CAPTIONER_ALT_LIMIT / CAPTIONER_ALT_SPEECH /
+// CAPTIONER_ALT_PEOPLE; per-shop policies from the TOML file at CAPTIONER_ALT_POLICIES; and a
+// request may override both via `alt_policy`.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub const DEFAULT_MAX_LEN: usize = 125;
+
+// Upper bound on `max_len` from any source; `slack` is bounded by `max_len`.
+pub const MAX_LEN_CAP: usize = 1000;
+
+// Words that must not end a truncated alt text.
+const CONNECTORS: &[&str] = &["with", "and", "or", "of", "on", "in", "at", "for", "to", "by", "from", "the", "a", "an"];
+const CLAUSE_MARKS: &[&str] = &[",", ";", ":", "(", "—", "–"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Limit {
+    // The word in progress at the limit may be finished if it ends within `slack` graphemes.
+    #[default]
+    Soft,
+    // Output never exceeds `max_len` graphemes.
+    Hard,
+}
+
+// How people in lifestyle shots are described (people.rs).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum People {
+    // Left out; the alt text is about the product only.
+    #[default]
+    Omit,
+    // "person" / "people", after the product ("Red dress worn by a person").
+    Neutral,
+    // The caption's own noun ("worn by a woman"), without age, body or ethnicity words.
+    AsCaptioned,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AltTextPolicy {
+    pub max_len: usize,
+    pub limit: Limit,
+    // Overrun allowed by a soft limit; defaults to 10% of max_len.
+    pub slack: usize,
+    // Spell out fractions, symbols and units for screen readers (speech.rs)
+    pub speech: bool,
+    pub people: People,
+}
+
+impl Default for AltTextPolicy {
+    fn default() -> Self {
+        AltTextPolicy::new(DEFAULT_MAX_LEN, Limit::Soft)
+    }
+}
+
+// Partial policy, as found in the request body or a shop's entry in the policy file.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolicyOverride {
+    pub max_len: Option<usize>,
+    pub limit: Option<Limit>,
+    pub slack: Option<usize>,
+    pub speech: Option<bool>,
+    pub people: Option<People>,
+}
+
+impl AltTextPolicy {
+    pub fn new(max_len: usize, limit: Limit) -> Self {
+        let max_len = max_len.clamp(1, MAX_LEN_CAP);
+        AltTextPolicy { max_len, limit, slack: max_len / 10, speech: false, people: People::Omit }
+    }
+
+    pub fn with(self, o: &PolicyOverride) -> Self {
+        let mut p = AltTextPolicy::new(o.max_len.unwrap_or(self.max_len), o.limit.unwrap_or(self.limit));
+        p.slack = o.slack.or(o.max_len.map(|_| p.slack)).unwrap_or(self.slack).min(p.max_len);
+        p.speech = o.speech.unwrap_or(self.speech);
+        p.people = o.people.unwrap_or(self.people);
+        p
+    }
+
+    // Fit `text` to the policy. Text already within the limit is returned trimmed but otherwise unchanged.
+    pub fn apply(&self, text: &str) -> String {
+        let text = text.trim();
+        let g: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+        if g.len() <= self.max_len {
+            return text.to_string();
+        }
+        let byte_at = |i: usize| g.get(i).map_or(text.len(), |(b, _)| *b);
+        let is_space = |i: usize| g.get(i).is_some_and(|(_, s)| s.chars().all(char::is_whitespace));
+
+        // End of the last clause that fits, as long as it keeps most of the text.
+        let clause = (self.max_len * 2 / 3..=self.max_len)
+            .rev()
+            .find(|&i| g.get(i).is_some_and(|(_, s)| CLAUSE_MARKS.contains(s)) || (is_space(i) && g.get(i + 1).is_some_and(|(_, s)| *s == "-") && is_space(i + 2)));
+        // Soft: finish the word in progress if it ends within the slack.
+        let finish_word = match self.limit {
+            Limit::Soft => (self.max_len..=self.max_len.saturating_add(self.slack)).find(|&i| i >= g.len() || is_space(i)),
+            Limit::Hard => None,
+        };
+        let word = (1..=self.max_len).rev().find(|&i| is_space(i));
+
+        // A soft limit keeps text that ends within the slack whole.
+        let whole = finish_word.filter(|&e| e >= g.len());
+        for end in [whole, clause, finish_word, word, Some(self.max_len)].into_iter().flatten() {
+            let cut = tidy_end(&text[..byte_at(end)]);
+            if !cut.is_empty() {
+                return cut;
+            }
+        }
+        // Only connectors and punctuation before the limit: cut mid-text rather than return nothing.
+        text[..byte_at(self.max_len)].trim_end().to_string()
+    }
+}
+
+// Drop trailing whitespace, clause punctuation and dangling connectors.
+fn tidy_end(s: &str) -> String {
+    let mut s = s;
+    loop {
+        let trimmed = s.trim_end().trim_end_matches([',', ';', ':', '(', '-', '—', '–', '/', '&']).trim_end();
+        let last = trimmed.unicode_words().next_back().unwrap_or("");
+        let dangling = !last.is_empty()
+            && trimmed.ends_with(last)
+            && CONNECTORS.iter().any(|c| c.eq_ignore_ascii_case(last))
+            && trimmed[..trimmed.len() - last.len()].chars().next_back().is_none_or(char::is_whitespace);
+        s = if dangling { &trimmed[..trimmed.len() - last.len()] } else { trimmed };
+        if !dangling {
+            return s.to_string();
+        }
+    }
+}
+
+// Default policy plus per-shop overrides.
+#[derive(Clone, Debug, Default)]
+pub struct PolicyBook {
+    default: AltTextPolicy,
+    shops: HashMap<String, PolicyOverride>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    default: PolicyOverride,
+    #[serde(default)]
+    shops: HashMap<String, PolicyOverride>,
+}
+
+impl PolicyBook {
+    pub fn from_env() -> Result<Self, String> {
+        let max_len = std::env::var("CAPTIONER_ALT_MAX_LEN").ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_MAX_LEN);
+        let limit = match std::env::var("CAPTIONER_ALT_LIMIT").as_deref() {
+            Ok("hard") => Limit::Hard,
+            _ => Limit::Soft,
+        };
+        let mut default = AltTextPolicy::new(max_len, limit);
+        default.speech = matches!(std::env::var("CAPTIONER_ALT_SPEECH").as_deref(), Ok("1" | "true"));
+        default.people = match std::env::var("CAPTIONER_ALT_PEOPLE").as_deref() {
+            Ok("neutral") => People::Neutral,
+            Ok("as_captioned") => People::AsCaptioned,
+            _ => People::Omit,
+        };
+        let mut book = PolicyBook { default, shops: HashMap::new() };
+        if let Ok(path) = std::env::var("CAPTIONER_ALT_POLICIES")
+            && !path.trim().is_empty()
+        {
+            let src = std::fs::read_to_string(path.trim()).map_err(|e| format!("{path}: {e}"))?;
+            book = book.merge_toml(&src).map_err(|e| format!("{path}: {e}"))?;
+        }
+        Ok(book)
+    }
+
+    fn merge_toml(mut self, src: &str) -> Result<Self, String> {
+        let file: PolicyFile = toml::from_str(src).map_err(|e| e.to_string())?;
+        self.default = self.default.with(&file.default);
+        self.shops = file.shops.into_iter().map(|(k, v)| (k.to_ascii_lowercase(), v)).collect();
+        Ok(self)
+    }
+
+    // Policy for a shop (e.g. "acme.myshopify.com") with an optional request override on top.
+    pub fn resolve(&self, shop: Option<&str>, request: Option<&PolicyOverride>) -> AltTextPolicy {
+        let mut p = self.default;
+        if let Some(o) = shop.and_then(|s| self.shops.get(&s.trim().to_ascii_lowercase())) {
+            p = p.with(o);
+        }
+        if let Some(o) = request {
+            p = p.with(o);
+        }
+        p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn hard(n: usize) -> AltTextPolicy {
+        AltTextPolicy::new(n, Limit::Hard)
+    }
+
+    #[test]
+    fn counts_graphemes_not_bytes() {
+        let text = "Crème brûlée ramekin in matte ivory";
+        assert_eq!(hard(35).apply(text), text);
+        assert_eq!(hard(12).apply("Crème brûlée ramekin"), "Crème brûlée");
+        // Flags and combining sequences are never split.
+        assert_eq!(hard(3).apply("🇫🇷🇫🇷🇫🇷🇫🇷"), "🇫🇷🇫🇷🇫🇷");
+        assert_eq!(hard(2).apply("e\u{301}e\u{301}e\u{301}"), "e\u{301}e\u{301}");
+    }
+
+    #[test]
+    fn prefers_clauses_then_words_and_drops_connectors() {
+        assert_eq!(hard(25).apply("Black leather tote, gold buckle and long strap"), "Black leather tote");
+        assert_eq!(hard(32).apply("Black leather tote, gold buckle and long strap"), "Black leather tote, gold buckle");
+        assert_eq!(hard(40).apply("Navy wool long-sleeve v-neck sweater with ribbed cuffs"), "Navy wool long-sleeve v-neck sweater");
+        assert_eq!(hard(24).apply("Red dress on a mannequin in a bright studio"), "Red dress on a mannequin");
+        assert_eq!(hard(11).apply("Red dress with pockets"), "Red dress");
+        assert_eq!(hard(5).apply("Supercalifragilistic"), "Super");
+    }
+
+    #[test]
+    fn soft_limit_finishes_the_word_in_progress() {
+        let soft = AltTextPolicy { max_len: 22, limit: Limit::Soft, slack: 4, speech: false, people: People::Omit };
+        assert_eq!(soft.apply("White ceramic coffee mugs on a shelf"), "White ceramic coffee mugs");
+        assert_eq!(soft.apply("White ceramic coffee mugs"), "White ceramic coffee mugs");
+        assert_eq!(hard(22).apply("White ceramic coffee mugs on a shelf"), "White ceramic coffee");
+    }
+
+    #[test]
+    fn resolves_shop_and_request_overrides() {
+        let book = PolicyBook::default()
+            .merge_toml("[default]\nmax_len = 120\n[shops.\"Acme.myshopify.com\"]\nmax_len = 80\nlimit = \"hard\"\n")
+            .unwrap();
+        assert_eq!(book.resolve(None, None).max_len, 120);
+        let shop = book.resolve(Some("acme.myshopify.com"), None);
+        assert_eq!((shop.max_len, shop.limit, shop.slack), (80, Limit::Hard, 8));
+        let req = PolicyOverride { max_len: Some(60), ..Default::default() };
+        assert_eq!(book.resolve(Some("acme.myshopify.com"), Some(&req)).limit, Limit::Hard);
+        assert_eq!(book.resolve(Some("acme.myshopify.com"), Some(&req)).max_len, 60);
+        assert!(PolicyBook::default().merge_toml("[default]\nmax = 3\n").is_err());
+        let book = PolicyBook::default().merge_toml("[shops.\"acme.myshopify.com\"]\nspeech = true\npeople = \"as_captioned\"\n").unwrap();
+        assert_eq!(book.resolve(Some("acme.myshopify.com"), None).people, People::AsCaptioned);
+        assert!(book.resolve(Some("acme.myshopify.com"), None).speech && !book.resolve(None, None).speech);
+        let off = PolicyOverride { speech: Some(false), ..Default::default() };
+        assert!(!book.resolve(Some("acme.myshopify.com"), Some(&off)).speech);
+    }
+
+    #[test]
+    fn clamps_request_lengths() {
+        let huge = PolicyOverride { max_len: Some(usize::MAX), slack: Some(usize::MAX), ..Default::default() };
+        let p = AltTextPolicy::default().with(&huge);
+        assert_eq!((p.max_len, p.slack), (MAX_LEN_CAP, MAX_LEN_CAP));
+        let p = AltTextPolicy::default().with(&PolicyOverride { slack: Some(usize::MAX), ..Default::default() });
+        assert_eq!((p.max_len, p.slack), (DEFAULT_MAX_LEN, DEFAULT_MAX_LEN));
+        // Policies built by hand are not clamped; apply must still not overflow.
+        let wide = AltTextPolicy { max_len: 3, slack: usize::MAX, ..AltTextPolicy::default() };
+        assert_eq!(wide.apply("Red dress"), "Red");
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_and_respects_the_limit(text in "\\PC{0,200}", max_len in 1usize..150, hard_limit: bool) {
+            let limit = if hard_limit { Limit::Hard } else { Limit::Soft };
+            let p = AltTextPolicy::new(max_len, limit);
+            let out = p.apply(&text);
+            let n = out.graphemes(true).count();
+            let bound = if hard_limit { max_len } else { max_len.saturating_add(p.slack) };
+            prop_assert!(n <= bound, "{n} > {bound}: {out:?}");
+            prop_assert!(text.trim().starts_with(&out), "{out:?} is not a prefix of {text:?}");
+            if text.trim().graphemes(true).count() <= max_len {
+                prop_assert_eq!(out, text.trim());
+            }
+        }
+
+        #[test]
+        fn never_ends_with_a_connector(words in prop::collection::vec("[a-zà-ÿ]{1,8}|with|and|of|in|the", 1..40), max_len in 5usize..60) {
+            let text = words.join(" ");
+            let out = hard(max_len).apply(&text);
+            if text.graphemes(true).count() > max_len {
+                let last = out.split_whitespace().next_back().unwrap_or("");
+                // Unless nothing but connectors fit before the limit.
+                let only_connectors = out.split_whitespace().all(|w| CONNECTORS.contains(&w));
+                prop_assert!(only_connectors || !CONNECTORS.contains(&last), "{out:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Alternative alt texts for merchants to choose from (`"candidates": N`). Alternatives come
+// from the remote model's beam search, the cleaned model caption, and refine_alt compositions
+// that leave one attribute out. Scores rank them, best first; near-duplicates are dropped.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+pub const MAX_CANDIDATES: usize = 10;
+
+// Relative scores: the chosen alt text first, then shorter compositions, then raw model text.
+pub const PRIMARY_SCORE: f32 = 1.0;
+const COMPOSITION_SCORE: f32 = 0.9;
+const MODEL_SCORE: f32 = 0.7;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub alt_text: String,
+    pub score: f32,
+    // How this candidate differs from the primary alt text
+    pub reason: String,
+}
+
+impl Candidate {
+    pub fn new(alt_text: String, score: f32, reason: impl Into<String>) -> Self {
+        Candidate { alt_text, score, reason: reason.into() }
+    }
+}
+
+// Composition leaving out `kept` of `total` attributes scores below the full one.
+pub fn composition_score(kept: usize, total: usize) -> f32 {
+    COMPOSITION_SCORE * (kept + 1) as f32 / (total + 1) as f32
+}
+
+// Beam-search alternatives keep their order; `score` is the model's sequence probability.
+pub fn model_score(rank: usize, score: Option<f32>) -> f32 {
+    MODEL_SCORE * score.unwrap_or(1.0).clamp(0.0, 1.0) / (rank + 1) as f32
+}
+
+// Up to `n` candidates, best first, keeping the better of any two near-duplicates.
+pub fn select(mut all: Vec<Candidate>, n: usize) -> Vec<Candidate> {
+    let n = n.min(MAX_CANDIDATES);
+    all.retain(|c| !c.alt_text.trim().is_empty());
+    // Stable, so equal scores keep the order they were offered in.
+    all.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut out: Vec<(Candidate, HashSet<String>)> = Vec::new();
+    for c in all {
+        let words = words(&c.alt_text);
+        if !out.iter().any(|(_, w)| *w == words) {
+            out.push((c, words));
+        }
+    }
+    out.truncate(n);
+    out.into_iter().map(|(c, _)| c).collect()
+}
+
+// Words up to case, plurals and articles: "Black shoes with a buckle" = "black shoe with buckle".
+fn words(text: &str) -> HashSet<String> {
+    crate::matching::tokenize(text).into_iter().filter(|w| !matches!(w.as_str(), "a" | "an" | "the")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_dedupes_and_caps() {
+        let all = vec![
+            Candidate::new("Black leather shoe".into(), composition_score(1, 2), "without detail"),
+            Candidate::new("Black leather shoe with buckle".into(), PRIMARY_SCORE, "primary"),
+            Candidate::new("black leather shoes with a buckle".into(), model_score(0, Some(0.9)), "model alternative"),
+            Candidate::new("A black shoe on a wooden floor".into(), model_score(1, None), "model alternative"),
+            Candidate::new("  ".into(), 2.0, "empty"),
+        ];
+        let got = select(all.clone(), 5);
+        let texts: Vec<&str> = got.iter().map(|c| c.alt_text.as_str()).collect();
+        assert_eq!(texts, ["Black leather shoe with buckle", "Black leather shoe", "A black shoe on a wooden floor"]);
+        assert_eq!(select(all, 1).len(), 1);
+    }
+}
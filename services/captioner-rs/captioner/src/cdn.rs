@@ -0,0 +1,149 @@
+// CDN-aware URL rewriting: ask image CDNs for a rendition close to the model input size
+// instead of downloading multi-megabyte originals only to resize them to 224px.
+
+use reqwest::Url;
+
+// Matches the square input produced by `preprocess_clip`.
+pub const MODEL_INPUT_PX: u32 = 224;
+
+#[derive(Clone, Debug)]
+pub struct CdnRule {
+    // Host (or ".suffix" for subdomains) the rule applies to.
+    pub host: String,
+    // Only rewrite paths under this prefix; `None` matches any path on the host.
+    pub path_prefix: Option<String>,
+    pub width_param: String,
+    pub height_param: String,
+}
+
+impl CdnRule {
+    fn new(host: &str, path_prefix: Option<&str>, width_param: &str, height_param: &str) -> Self {
+        CdnRule {
+            host: host.to_ascii_lowercase(),
+            path_prefix: path_prefix.map(str::to_string),
+            width_param: width_param.into(),
+            height_param: height_param.into(),
+        }
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str().map(|h| h.to_ascii_lowercase()) else { return false };
+        let host_ok = match self.host.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(self.host.as_str()),
+            None => host == self.host,
+        };
+        host_ok && self.path_prefix.as_deref().is_none_or(|p| url.path().starts_with(p))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CdnRewriter {
+    rules: Vec<CdnRule>,
+    target_px: u32,
+}
+
+impl CdnRewriter {
+    // Shopify serves product media from cdn.shopify.com and from `/cdn/shop/` on shop
+    // domains; both accept `width`/`height` and keep the aspect ratio within the box.
+    pub fn shopify(target_px: u32) -> Self {
+        CdnRewriter {
+            rules: vec![
+                CdnRule::new("cdn.shopify.com", Some("/s/files/"), "width", "height"),
+                CdnRule::new(".myshopify.com", Some("/cdn/shop/"), "width", "height"),
+            ],
+            target_px,
+        }
+    }
+
+    // Extra rules come from CAPTIONER_CDN_RULES as comma-separated
+    // `host[/path-prefix]=width_param:height_param`, e.g. `.imgix.net=w:h`.
+    pub fn from_env() -> Self {
+        let target_px = std::env::var("CAPTIONER_CDN_TARGET_PX")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(MODEL_INPUT_PX);
+        if matches!(std::env::var("CAPTIONER_CDN_REWRITE").ok().as_deref(), Some("0") | Some("false")) {
+            return CdnRewriter { rules: vec![], target_px };
+        }
+        let mut rw = Self::shopify(target_px);
+        if let Ok(spec) = std::env::var("CAPTIONER_CDN_RULES") {
+            for rule in spec.split(',').filter_map(parse_rule) {
+                rw = rw.with_rule(rule);
+            }
+        }
+        rw
+    }
+
+    pub fn with_rule(mut self, rule: CdnRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    // Returns the right-sized URL, or `None` when no rule applies or the URL already
+    // pins a size (the merchant or caller chose it deliberately).
+    pub fn rewrite(&self, url: &Url) -> Option<Url> {
+        let rule = self.rules.iter().find(|r| r.matches(url))?;
+        if url.query_pairs().any(|(k, _)| k == rule.width_param || k == rule.height_param) {
+            return None;
+        }
+        let px = self.target_px.to_string();
+        let mut out = url.clone();
+        out.query_pairs_mut()
+            .append_pair(&rule.width_param, &px)
+            .append_pair(&rule.height_param, &px);
+        Some(out)
+    }
+}
+
+fn parse_rule(spec: &str) -> Option<CdnRule> {
+    let (target, params) = spec.trim().split_once('=')?;
+    let (w, h) = params.split_once(':')?;
+    let (host, prefix) = match target.find('/') {
+        Some(i) => (&target[..i], Some(&target[i..])),
+        None => (target, None),
+    };
+    if host.is_empty() || w.is_empty() || h.is_empty() {
+        tracing::warn!(rule = %spec, "ignoring malformed CDN rule");
+        return None;
+    }
+    Some(CdnRule::new(host, prefix, w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn rewrites_shopify_cdn_urls() {
+        let rw = CdnRewriter::shopify(224);
+        let out = rw.rewrite(&url("https://cdn.shopify.com/s/files/1/0001/products/shoe.jpg?v=1699")).unwrap();
+        assert_eq!(out.as_str(), "https://cdn.shopify.com/s/files/1/0001/products/shoe.jpg?v=1699&width=224&height=224");
+        let out = rw.rewrite(&url("https://acme.myshopify.com/cdn/shop/files/bag.png")).unwrap();
+        assert_eq!(out.query(), Some("width=224&height=224"));
+    }
+
+    #[test]
+    fn leaves_other_and_presized_urls_alone() {
+        let rw = CdnRewriter::shopify(224);
+        assert!(rw.rewrite(&url("https://example.com/s/files/shoe.jpg")).is_none());
+        assert!(rw.rewrite(&url("https://cdn.shopify.com/shopifycloud/brochure/x.png")).is_none());
+        assert!(rw.rewrite(&url("https://cdn.shopify.com/s/files/1/shoe.jpg?width=800")).is_none());
+    }
+
+    #[test]
+    fn parses_configured_rules() {
+        let r = parse_rule(" .imgix.net=w:h ").unwrap();
+        assert_eq!((r.host.as_str(), r.path_prefix, r.width_param.as_str()), (".imgix.net", None, "w"));
+        let r = parse_rule("images.example.com/media/=width:height").unwrap();
+        assert_eq!(r.path_prefix.as_deref(), Some("/media/"));
+        assert!(parse_rule("bogus").is_none());
+
+        let rw = CdnRewriter::shopify(224).with_rule(parse_rule(".imgix.net=w:h").unwrap());
+        let out = rw.rewrite(&url("https://acme.imgix.net/p/1.jpg")).unwrap();
+        assert_eq!(out.query(), Some("w=224&h=224"));
+    }
+}
@@ -0,0 +1,99 @@
+// CLIP text encoder for reranking candidate captions against the image embedding the visual
+// model already computes. Optional: without the textual model and tokenizer files next to the
+// visual model, reranking is skipped.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use ndarray::{CowArray, IxDyn};
+use ort::{session::Session, tensor::OrtOwnedTensor, value::Value};
+use tokenizers::Tokenizer;
+
+use crate::engine;
+
+// CLIP's fixed text context; longer texts are truncated, keeping the end-of-text token.
+const CONTEXT_LEN: usize = 77;
+
+pub struct TextEncoder {
+    session: Session,
+    tokenizer: Tokenizer,
+}
+
+impl TextEncoder {
+    pub fn load(model_path: &str, tokenizer_path: &str) -> anyhow::Result<Self> {
+        for path in [model_path, tokenizer_path] {
+            if !Path::new(path).is_file() {
+                anyhow::bail!("not found: {path}");
+            }
+        }
+        let tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| anyhow!("{tokenizer_path}: {e}"))?;
+        let session = engine::build_session(model_path)?;
+        Ok(TextEncoder { session, tokenizer })
+    }
+
+    // One L2-normalized embedding per text.
+    pub fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut ids = vec![0i64; texts.len() * CONTEXT_LEN];
+        for (row, text) in ids.chunks_mut(CONTEXT_LEN).zip(texts) {
+            let enc = self.tokenizer.encode(text.to_lowercase(), true).map_err(|e| anyhow!(e))?;
+            let toks = enc.get_ids();
+            for (slot, &id) in row.iter_mut().zip(toks) {
+                *slot = i64::from(id);
+            }
+            if toks.len() > CONTEXT_LEN
+                && let Some(&eot) = toks.last()
+            {
+                row[CONTEXT_LEN - 1] = i64::from(eot);
+            }
+        }
+        let arr = ndarray::Array::from_shape_vec((texts.len(), CONTEXT_LEN), ids)?;
+        let cow = CowArray::from(arr.into_dyn());
+        let val = Value::from_array(self.session.allocator(), &cow)?;
+        let outputs = self.session.run(vec![val])?;
+        let emb: OrtOwnedTensor<'_, f32, IxDyn> = outputs[0].try_extract()?;
+        let flat: Vec<f32> = emb.view().iter().copied().collect();
+        let dim = flat.len() / texts.len();
+        Ok(flat.chunks(dim.max(1)).map(normalized).collect())
+    }
+}
+
+fn normalized(v: &[f32]) -> Vec<f32> {
+    let n = v.iter().map(|x| x * x).sum::<f32>().sqrt().max(1e-12);
+    v.iter().map(|x| x / n).collect()
+}
+
+// Cosine similarity of two L2-normalized vectors.
+pub fn similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// Index of the best-aligned text. Index 0 (the refined alt text) is kept unless another text
+// beats it by more than `margin`; CLIP similarities sit close together, so small wins are noise.
+pub fn best_aligned(scores: &[f32], margin: f32) -> usize {
+    let Some(&incumbent) = scores.first() else {
+        return 0;
+    };
+    let (best, &score) = scores
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .expect("non-empty");
+    if score > incumbent + margin { best } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_refined_text_unless_clearly_beaten() {
+        assert_eq!(best_aligned(&[0.30, 0.305, 0.29], 0.01), 0);
+        assert_eq!(best_aligned(&[0.24, 0.31, 0.29], 0.01), 1);
+        assert_eq!(best_aligned(&[], 0.01), 0);
+        let v = normalized(&[3.0, 4.0]);
+        assert!((similarity(&v, &v) - 1.0).abs() < 1e-6);
+    }
+}
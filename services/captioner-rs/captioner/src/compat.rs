@@ -0,0 +1,171 @@
+// Drop-in replacement for the Python worker's `POST /caption` (apps/worker/main.py), so
+// WORKER_URL can point at this service: same `{image_url, title, vendor}` body, same
+// `{alt_text}` response, and a caption even when the image can't be fetched or captioned.
+//
+// Matches the worker: the fallback is the title as sent plus "product photo", cut at 140
+// characters, and a body FastAPI would refuse gets 422 with its `{"detail": [...]}` shape.
+// Deviates on purpose: a fetched image is described by the standard pipeline (shop length
+// policy, no "square 100x100px"), and the image is fetched under the Image Fetch Policy, so a
+// URL it refuses gets the fallback rather than a request from inside the network.
+
+use std::sync::{Arc, atomic::Ordering};
+
+use axum::{
+    Json,
+    extract::{State, rejection::JsonRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{AppState, CaptionReq};
+
+// The worker cut every caption, fallback included, at this many characters.
+const WORKER_MAX_LEN: usize = 140;
+
+#[derive(Deserialize)]
+pub struct WorkerCaptionIn {
+    image_url: Option<String>,
+    title: Option<String>,
+    vendor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WorkerCaptionOut {
+    alt_text: String,
+}
+
+// One entry of FastAPI's validation error list.
+#[derive(Serialize)]
+struct ValidationError {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    loc: Vec<&'static str>,
+    msg: String,
+}
+
+fn unprocessable(kind: &'static str, loc: Vec<&'static str>, msg: String) -> Response {
+    let detail = [ValidationError { kind, loc, msg }];
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({ "detail": detail }))).into_response()
+}
+
+pub async fn caption(State(state): State<Arc<AppState>>, body: Result<Json<WorkerCaptionIn>, JsonRejection>) -> Response {
+    let inp = match body {
+        Ok(Json(inp)) => inp,
+        Err(e) => return unprocessable("json_invalid", vec!["body"], e.body_text()),
+    };
+    // The worker's schema only accepted http(s) URLs.
+    let Some(image_url) = inp.image_url else {
+        return unprocessable("missing", vec!["body", "image_url"], "Field required".into());
+    };
+    match reqwest::Url::parse(&image_url) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") && u.has_host() => {}
+        Ok(_) => return unprocessable("url_scheme", vec!["body", "image_url"], "URL scheme should be 'http' or 'https'".into()),
+        Err(_) => return unprocessable("url_parsing", vec!["body", "image_url"], "Input should be a valid URL".into()),
+    }
+    state.request_count.fetch_add(1, Ordering::Relaxed);
+
+    let fallback = fallback_alt(inp.title.as_deref());
+    let req = CaptionReq { image_url, product_title: inp.title, vendor: inp.vendor, ..Default::default() };
+    let alt_text = match state.pipeline.run(&state, req).await {
+        // Without the image the worker answered with its fallback, not a template.
+        Ok(ctx) if !ctx.image_unavailable && !ctx.alt.trim().is_empty() => ctx.alt,
+        Ok(_) => fallback,
+        Err(e) => {
+            tracing::warn!(err = %e, "compat caption failed; returning title fallback");
+            fallback
+        }
+    };
+    Json(WorkerCaptionOut { alt_text }).into_response()
+}
+
+// The worker's fallback, "<title>, product photo", title as sent.
+fn fallback_alt(title: Option<&str>) -> String {
+    let core: Vec<&str> = title.map(str::trim).into_iter().chain(["product photo"]).collect();
+    core.join(", ").chars().take(WORKER_MAX_LEN).collect()
+}
+
+// Mirrors apps/worker/tests/test_caption_endpoint.py.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fetch, pipeline, url_policy};
+    use axum::{Router, body::Body, http::Request, routing::{get, post}};
+    use tower::ServiceExt;
+
+    // The pipeline /v1/caption runs; the stub engine tags every image "red shoe".
+    fn app() -> Router {
+        let mut state = crate::tests::dummy_state_with(pipeline::CaptionPipeline::standard());
+        Arc::get_mut(&mut state).unwrap().fetcher =
+            fetch::Fetcher::new(url_policy::UrlPolicy { allow_loopback: true, ..Default::default() }).unwrap();
+        Router::new().route("/caption", post(caption)).with_state(state)
+    }
+
+    async fn serve_image() -> String {
+        let app = Router::new().route("/image.png", get(|| async { crate::tests::SAMPLE_JPG }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    async fn post_json(body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let resp = app()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/caption")
+                    .header("content-type", "application/json")
+                    .body(Body::from(serde_json::to_vec(&body).unwrap()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    // The worker also appended "square 100x100px"; captions here describe the product instead.
+    #[tokio::test]
+    async fn caption_endpoint_strips_vendor() {
+        let base = serve_image().await;
+        let (status, v) = post_json(serde_json::json!({
+            "image_url": format!("{base}/image.png"),
+            "title": "ACME Canvas Tote",
+            "vendor": "ACME",
+        }))
+        .await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["alt_text"], "Red canvas tote");
+    }
+
+    #[tokio::test]
+    async fn caption_endpoint_fallback_on_failure() {
+        let base = serve_image().await;
+        let (status, v) = post_json(serde_json::json!({"image_url": format!("{base}/missing.png"), "title": "Example", "vendor": null})).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["alt_text"], "Example, product photo");
+        let title = format!("ACME {}", "Widget ".repeat(30));
+        let (_, v) = post_json(serde_json::json!({"image_url": format!("{base}/missing.png"), "title": title, "vendor": "ACME"})).await;
+        let alt = v["alt_text"].as_str().unwrap();
+        assert!(alt.starts_with("ACME Widget") && alt.chars().count() == WORKER_MAX_LEN, "{alt}");
+
+        let (status, v) = post_json(serde_json::json!({"image_url": "http://169.254.169.254/x.png"})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["alt_text"], "product photo");
+    }
+
+    #[tokio::test]
+    async fn caption_endpoint_rejects_what_the_worker_rejected() {
+        let (status, v) = post_json(serde_json::json!({"title": "No image"})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(v["detail"][0], serde_json::json!({"type": "missing", "loc": ["body", "image_url"], "msg": "Field required"}));
+        let (status, v) = post_json(serde_json::json!({"image_url": "s3://bucket/key.png"})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(v["detail"][0]["type"], "url_scheme");
+        let (status, v) = post_json(serde_json::json!({"image_url": 7})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(v["detail"][0]["loc"], serde_json::json!(["body"]));
+    }
+}
@@ -0,0 +1,259 @@
+// Noun-phrase composition for refined alt text: orders modifiers the way English does
+// (opinion, size, age, shape, color, pattern, origin, material, purpose), attaches detail
+// nouns with "with", and handles articles, plural-only nouns and sentence casing.
+
+use serde::Deserialize;
+
+// The full English order; taxonomy.toml names slots in lowercase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Slot {
+    Opinion,
+    Size,
+    Age,
+    Shape,
+    Color,
+    Pattern,
+    Origin,
+    Material,
+    Purpose,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Phrase {
+    noun: String,
+    modifiers: Vec<(Slot, String)>,
+    details: Vec<String>,
+    // Leads the modifiers as written: "Nike black leather sneaker".
+    brand: Option<String>,
+    article: bool,
+}
+
+impl Phrase {
+    pub fn new(noun: &str) -> Self {
+        Phrase { noun: noun.trim().to_string(), ..Default::default() }
+    }
+
+    // Multi-word modifiers in front of a noun are hyphenated: "long sleeve" → "long-sleeve".
+    pub fn modifier(self, slot: Slot, word: &str) -> Self {
+        let compound = word.split_whitespace().collect::<Vec<_>>().join("-");
+        self.push_modifier(slot, &compound)
+    }
+
+    // A multi-word modifier that stays an open compound: "stainless steel", "earl grey".
+    pub fn open_modifier(self, slot: Slot, word: &str) -> Self {
+        let word = word.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.push_modifier(slot, &word)
+    }
+
+    fn push_modifier(mut self, slot: Slot, word: &str) -> Self {
+        let dup = self.modifiers.iter().any(|(_, w)| w.eq_ignore_ascii_case(word));
+        if !word.is_empty() && !dup && !self.noun.eq_ignore_ascii_case(word) {
+            self.modifiers.push((slot, word.to_string()));
+        }
+        self
+    }
+
+    // A feature attached after the noun: "shoe with a buckle".
+    pub fn detail(mut self, noun: &str) -> Self {
+        let noun = noun.trim();
+        if !noun.is_empty() && !self.details.iter().any(|d| d.eq_ignore_ascii_case(noun)) {
+            self.details.push(noun.to_string());
+        }
+        self
+    }
+
+    pub fn brand(mut self, name: &str) -> Self {
+        let name = name.trim();
+        if !name.is_empty() {
+            self.brand = Some(name.to_string());
+        }
+        self
+    }
+
+    // Lead with "a"/"an", or "a pair of" for plural-only nouns. Alt text leaves it off.
+    pub fn with_article(mut self) -> Self {
+        self.article = true;
+        self
+    }
+
+    pub fn compose(&self) -> String {
+        let mut mods = self.modifiers.clone();
+        // Stable: modifiers sharing a slot keep insertion order.
+        mods.sort_by_key(|(slot, _)| *slot);
+
+        let mut words: Vec<String> = self.brand.iter().cloned().collect();
+        words.extend(mods.into_iter().map(|(_, w)| w));
+        words.push(self.noun.clone());
+        let mut out = words.join(" ");
+
+        if self.article {
+            out = if is_plural_noun(&self.noun) {
+                format!("a pair of {out}")
+            } else {
+                format!("{} {out}", indefinite_article(&out))
+            };
+        }
+
+        // Details always take an article, led or not: "shoe with a buckle", "case with earbuds".
+        if !self.details.is_empty() {
+            let details: Vec<String> = self
+                .details
+                .iter()
+                .map(|d| if is_plural_noun(d) { d.clone() } else { format!("{} {d}", indefinite_article(d)) })
+                .collect();
+            out.push_str(" with ");
+            out.push_str(&join_and(&details));
+        }
+        sentence_case(&out)
+    }
+}
+
+pub fn join_and(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.clone(),
+        [init @ .., last] => format!("{} and {last}", init.join(", ")),
+    }
+}
+
+// Nouns that only exist in the plural and take "a pair of" rather than "a".
+pub fn is_plural_noun(noun: &str) -> bool {
+    const PLURALIA: &[&str] = &[
+        "pants", "jeans", "trousers", "slacks", "chinos", "leggings", "shorts", "tights", "sunglasses",
+        "glasses", "earrings", "headphones", "earbuds", "scissors", "pajamas",
+    ];
+    let last = noun.rsplit(' ').next().unwrap_or(noun).to_lowercase();
+    PLURALIA.contains(&last.as_str())
+}
+
+pub fn indefinite_article(phrase: &str) -> &'static str {
+    let first = phrase.split_whitespace().next().unwrap_or("").to_lowercase();
+    // Exceptions where spelling and sound disagree.
+    const AN_CONSONANT: &[&str] = &["hour", "honest", "honor", "heir", "herb"];
+    const A_VOWEL: &[&str] = &["uni", "use", "usu", "uti", "eu", "one", "once", "ewe"];
+    if AN_CONSONANT.iter().any(|p| first.starts_with(p)) {
+        return "an";
+    }
+    if A_VOWEL.iter().any(|p| first.starts_with(p)) {
+        return "a";
+    }
+    // Numerals read as "eight", "eleven", "eighteen", "eighty".
+    if first.starts_with('8') || first == "11" || first == "18" || first.starts_with("11-") || first.starts_with("18-") {
+        return "an";
+    }
+    match first.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+        _ => "a",
+    }
+}
+
+pub fn sentence_case(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_modifiers_and_attaches_details() {
+        let cases: &[(Phrase, &str)] = &[
+            (Phrase::new("shoe").modifier(Slot::Material, "leather").modifier(Slot::Color, "black").detail("buckle"), "Black leather shoe with a buckle"),
+            (Phrase::new("sweater").modifier(Slot::Purpose, "long sleeve").modifier(Slot::Purpose, "v-neck").modifier(Slot::Material, "wool").modifier(Slot::Color, "navy"), "Navy wool long-sleeve v-neck sweater"),
+            (Phrase::new("bottle").modifier(Slot::Purpose, "insulated").open_modifier(Slot::Material, "stainless steel").modifier(Slot::Opinion, "matte").modifier(Slot::Color, "black"), "Matte black stainless steel insulated bottle"),
+            (Phrase::new("bag").detail("zipper").detail("strap").detail("logo"), "Bag with a zipper, a strap and a logo"),
+            (Phrase::new("case").modifier(Slot::Color, "white").detail("earbuds").detail("cable"), "White case with earbuds and a cable"),
+            (Phrase::new("dress").modifier(Slot::Color, "red").modifier(Slot::Color, "Red"), "Red dress"),
+            (Phrase::new("tote").modifier(Slot::Material, "tote"), "Tote"),
+        ];
+        for (p, want) in cases {
+            assert_eq!(p.compose(), *want);
+        }
+    }
+
+    #[test]
+    fn articles_and_plurals() {
+        let cases: &[(Phrase, &str)] = &[
+            (Phrase::new("dress").modifier(Slot::Color, "ivory").with_article(), "An ivory dress"),
+            (Phrase::new("mug").modifier(Slot::Material, "ceramic").with_article().detail("logo"), "A ceramic mug with a logo"),
+            (Phrase::new("jeans").modifier(Slot::Color, "blue").with_article(), "A pair of blue jeans"),
+            (Phrase::new("headphones").with_article().detail("earbuds"), "A pair of headphones with earbuds"),
+            (Phrase::new("hat").modifier(Slot::Color, "orange").with_article(), "An orange hat"),
+            (Phrase::new("case").modifier(Slot::Purpose, "unisex").with_article(), "A unisex case"),
+            (Phrase::new("sneaker").modifier(Slot::Color, "white").brand("Allbirds").with_article(), "An Allbirds white sneaker"),
+        ];
+        for (p, want) in cases {
+            assert_eq!(p.compose(), *want);
+        }
+        assert_eq!(indefinite_article("hour-long"), "an");
+        assert_eq!(indefinite_article("8-piece"), "an");
+        assert_eq!(indefinite_article("one-shoulder"), "a");
+    }
+
+    // Every value of every attribute type that applies to each category, placed along with a
+    // value of each of the category's other attribute types: modifiers in slot order before the
+    // noun, details after "with" with their articles, the leading article, sentence case.
+    #[test]
+    fn composes_every_category_and_attribute_value() {
+        let tax = crate::taxonomy::Taxonomy::builtin();
+        let mut composed = 0;
+        for cat in tax.categories() {
+            let attrs: Vec<_> = tax.attributes_of(cat).filter(|(_, a)| !a.is_measure()).collect();
+            for (i, (_, attr)) in attrs.iter().enumerate() {
+                for value in attr.values() {
+                    // One value per other attribute type, skipping words already used
+                    let mut picked = vec![(*attr, value)];
+                    for (j, (_, other)) in attrs.iter().enumerate().filter(|&(j, _)| j != i) {
+                        let used = |w: &str| w == cat.name || picked.iter().any(|(a, v)| a.form_of(v) == w || *v == w);
+                        if let Some(v) = other.values().into_iter().cycle().skip(j).take(other.values().len()).find(|v| !used(v) && !used(other.form_of(v))) {
+                            picked.push((*other, v));
+                        }
+                    }
+                    let phrase = picked.iter().fold(Phrase::new(&cat.name), |p, (a, v)| a.place(p, v));
+                    check(&cat.name, &picked, &phrase);
+                    composed += 1;
+                }
+            }
+        }
+        assert!(composed > 200, "{composed} phrases");
+
+        fn check(noun: &str, picked: &[(&crate::taxonomy::Attribute, &str)], phrase: &Phrase) {
+            let bare = phrase.compose();
+            let led = phrase.clone().with_article().compose();
+            let values: Vec<&str> = picked.iter().map(|(_, v)| *v).collect();
+            let ctx = format!("{noun} {values:?} -> {bare:?} / {led:?}");
+            let mut chars = bare.chars();
+            assert!(chars.next().is_some_and(char::is_uppercase), "{ctx}");
+            assert_eq!(chars.as_str(), chars.as_str().to_lowercase(), "{ctx}");
+
+            let lower = bare.to_lowercase();
+            let (head, tail) = lower.split_once(" with ").unwrap_or((&lower, ""));
+            assert!(head.ends_with(noun), "{ctx}");
+            let head = format!(" {head} ");
+            let mut slots = Vec::new();
+            for (attr, v) in picked {
+                let form = attr.form_of(v);
+                match attr.slot_of(v) {
+                    Some(slot) => {
+                        let written = if attr.is_open(v) { form.to_string() } else { form.replace(' ', "-") };
+                        let at = head.find(&format!(" {written} ")).expect(&ctx);
+                        assert!(at + written.len() < head.len() - noun.len() - 1, "{ctx}");
+                        slots.push((at, slot));
+                    }
+                    None if is_plural_noun(form) => assert!(tail.contains(form), "{ctx}"),
+                    None => assert!(tail.contains(&format!("{} {form}", indefinite_article(form))), "{ctx}"),
+                }
+            }
+            slots.sort();
+            assert!(slots.windows(2).all(|w| w[0].1 <= w[1].1), "{ctx}");
+
+            let lead = if is_plural_noun(noun) { "a pair of" } else { indefinite_article(&lower) };
+            assert_eq!(led, sentence_case(&format!("{lead} {lower}")), "{ctx}");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// Long descriptions (`"long_description": true`) for images a short alt text can't cover:
+// size charts, infographics, multi-item bundles. A few plain sentences built from the refined
+// subject, the model caption, product context, model tags and any text a remote endpoint read
+// in the image. They are exempt from the alt text policy and capped by
+// CAPTIONER_LONG_DESC_MAX_LEN instead, dropping the least useful sentences first.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::alt_policy::{AltTextPolicy, Limit};
+use crate::compose::{self, Phrase};
+use crate::matching::Tokens;
+use crate::taxonomy::Taxonomy;
+use crate::trace::RefineTrace;
+
+pub const DEFAULT_MAX_LEN: usize = 500;
+
+// Model tags listed in the "Also visible" sentence.
+const MAX_TAGS: usize = 5;
+
+pub struct Facts<'a> {
+    // Opening sentence, e.g. "A navy cotton t-shirt with a pocket"
+    pub subject: String,
+    // Cleaned model caption; empty when no model answered
+    pub caption: &'a str,
+    pub title: Option<&'a str>,
+    pub vendor: Option<&'a str>,
+    pub product_type: Option<&'a str>,
+    pub options: Vec<(&'static str, &'a str)>,
+    pub tags: &'a [String],
+    // Text read from the image, one entry per line or block
+    pub text: &'a [String],
+}
+
+// The refined category and attributes as a full noun phrase with articles, or `fallback`
+// (the short alt text) when refinement found no category.
+pub fn subject(tax: &Taxonomy, refine: Option<&RefineTrace>, brand: Option<&str>, fallback: &str) -> String {
+    let Some(category) = refine.and_then(|r| r.category.as_deref()) else {
+        return fallback.to_string();
+    };
+    let mut phrase = Phrase::new(category);
+    for choice in refine.map_or(&[][..], |r| &r.attributes) {
+        if let Some(attr) = tax.attribute(&choice.attribute) {
+            phrase = attr.place(phrase, &choice.value);
+        }
+    }
+    if let Some(b) = brand {
+        phrase = phrase.brand(b);
+    }
+    phrase.with_article().compose()
+}
+
+pub fn compose(f: &Facts, max_len: usize) -> String {
+    // (priority, sentence) in reading order; the highest priority number is dropped first.
+    let mut sentences: Vec<(u8, String)> = vec![(0, sentence(&f.subject))];
+
+    let text: Vec<&str> = f.text.iter().map(|t| t.trim()).filter(|t| !t.is_empty()).collect();
+    if !text.is_empty() {
+        sentences.push((1, format!("Text in the image reads: \"{}\".", text.join(" / "))));
+    }
+
+    if let Some(title) = f.title.map(str::trim).filter(|t| !t.is_empty()) {
+        let by = f.vendor.map(str::trim).filter(|v| !v.is_empty() && !Tokens::new(title).contains(v));
+        let product = match by {
+            Some(v) => format!("Product: {title}, by {v}"),
+            None => format!("Product: {title}"),
+        };
+        sentences.push((2, sentence(&product)));
+    }
+    if let Some(t) = f.product_type.map(str::trim).filter(|t| !t.is_empty()) {
+        sentences.push((4, format!("Category: {}.", t.to_lowercase())));
+    }
+    let options: Vec<String> = f.options.iter().filter(|(_, v)| !v.trim().is_empty()).map(|(k, v)| format!("{k} {}", v.trim())).collect();
+    if !options.is_empty() {
+        sentences.push((3, format!("Options shown: {}.", options.join(", "))));
+    }
+
+    let caption = f.caption.trim();
+    let said = sentences.iter().map(|(_, s)| s.as_str()).collect::<Vec<_>>().join(" ");
+    if !caption.is_empty() && !Tokens::new(&said).contains(caption) {
+        sentences.push((5, format!("The photo shows {}.", lowercase_first(caption.trim_end_matches('.')))));
+    }
+
+    let said = sentences.iter().map(|(_, s)| s.as_str()).collect::<Vec<_>>().join(" ");
+    let seen = Tokens::new(&said);
+    let mut extra: Vec<String> = Vec::new();
+    for t in f.tags.iter().map(|t| t.trim().to_lowercase()) {
+        if !t.is_empty() && !seen.contains(&t) && !extra.contains(&t) && extra.len() < MAX_TAGS {
+            extra.push(t);
+        }
+    }
+    if !extra.is_empty() {
+        sentences.push((6, format!("Also visible: {}.", compose::join_and(&extra))));
+    }
+
+    let len = |s: &[(u8, String)]| s.iter().map(|(_, t)| t.graphemes(true).count() + 1).sum::<usize>().saturating_sub(1);
+    while sentences.len() > 1 && len(&sentences) > max_len {
+        let drop = sentences.iter().enumerate().max_by_key(|(_, (p, _))| *p).map(|(i, _)| i).unwrap_or(0);
+        sentences.remove(drop);
+    }
+    let out = sentences.into_iter().map(|(_, s)| s).collect::<Vec<_>>().join(" ");
+    AltTextPolicy::new(max_len, Limit::Hard).apply(&out)
+}
+
+fn sentence(s: &str) -> String {
+    let s = compose::sentence_case(s.trim());
+    if s.ends_with(['.', '!', '?']) { s } else { format!("{s}.") }
+}
+
+// "A red shoe" -> "a red shoe", leaving acronyms ("USB cable") alone.
+fn lowercase_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), Some(d)) if c.is_uppercase() && !d.is_uppercase() => c.to_lowercase().chain(s[c.len_utf8()..].chars()).collect(),
+        _ => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_sentences_and_drops_the_least_useful_first() {
+        let tags = vec!["shoe".to_string(), "Buckle".to_string(), "box".to_string(), "laces".to_string()];
+        let text = vec!["EU 42".to_string(), "US 9 ".to_string()];
+        let f = Facts {
+            subject: "A black leather shoe with a buckle".into(),
+            caption: "A shoe next to a cardboard box",
+            title: Some("ACME Oxford"),
+            vendor: Some("ACME"),
+            product_type: Some("Shoes"),
+            options: vec![("size", "9")],
+            tags: &tags,
+            text: &text,
+        };
+        let full = compose(&f, DEFAULT_MAX_LEN);
+        assert_eq!(
+            full,
+            "A black leather shoe with a buckle. Text in the image reads: \"EU 42 / US 9\". Product: ACME Oxford. \
+             Category: shoes. Options shown: size 9. The photo shows a shoe next to a cardboard box. Also visible: laces."
+        );
+
+        let short = compose(&f, 100);
+        assert_eq!(short, "A black leather shoe with a buckle. Text in the image reads: \"EU 42 / US 9\". Product: ACME Oxford.");
+        assert!(compose(&f, 20).graphemes(true).count() <= 20);
+    }
+}
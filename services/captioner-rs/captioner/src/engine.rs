@@ -1,31 +1,33 @@
 use crate::ApiError;
-use anyhow;
-use image::{DynamicImage, GenericImageView};
+use image::DynamicImage;
 use ort::{
     execution_providers::{
         CPUExecutionProviderOptions, ExecutionProvider,
-    }, session::{self, Session}, Environment
+    }, session::Session, Environment
 };
-use ndarray::{Array4, IxDyn, CowArray};
+use ndarray::{IxDyn, CowArray};
 use ort::value::Value;
-use ort::tensor::OrtOwnedTensor;
+use std::sync::Arc;
 
-#[cfg(feature = "cuda")]
+#[cfg(feature = "accel-cuda")]
 use ort::execution_providers::CUDAExecutionProviderOptions;
 
-#[cfg(feature = "coreml")]
+#[cfg(feature = "accel-coreml")]
 use ort::execution_providers::CoreMLExecutionProviderOptions;
 
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{Mutex, mpsc, oneshot};
 
 pub struct Job {
     pub image: DynamicImage,
     pub tx: oneshot::Sender<Result<EngineOutput, ApiError>>,
 }
 
+// What inference found about an image. The local CLIP visual model only embeds it (for
+// reranking); `caption` and `tags` come from remote endpoints and are empty locally.
 pub struct EngineOutput {
-    pub embed_dim: usize,
     pub embedding: Vec<f32>,
+    pub caption: String,
+    pub tags: Vec<String>,
 }
 
 pub struct Engine {
@@ -38,43 +40,45 @@ impl Engine {
     }
 }
 
-pub fn spawn(queue_cap: usize, model_path: &str) -> Engine {
-    let (tx, mut rx) = mpsc::channel::<Job>(queue_cap);
-
-    let session = build_session(model_path).expect("onnx session");
-
-    tokio::spawn(async move {
-        while let Some(Job { image, tx }) = rx.recv().await {
-            let out = infer_clip(&session, &image).await;
-            let _ = tx.send(out);
-        }
-    });
-
-    Engine { tx }
-}
-
-fn build_session(model_path: &str) -> anyhow::Result<Session> {
-    let mut eps: Vec<ExecutionProvider> = Vec::<ExecutionProvider>::new();
-
-    #[cfg(feature = "cuda")]
-    {
-        let cuda = ExecutionProvider::CUDA(CUDAExecutionProviderOptions::default());
-        if cuda.is_available() {
-            eps.push(cuda);
-        }
+// `workers` jobs run at once, sharing one session, on the blocking pool.
+// Fails when the model file is missing or can't be loaded; the server then runs without a local model.
+pub fn spawn(queue_cap: usize, workers: usize, model_path: &str) -> anyhow::Result<Engine> {
+    if !std::path::Path::new(model_path).is_file() {
+        anyhow::bail!("model file not found: {model_path}");
     }
-    #[cfg(feature = "coreml")]
-    {
-
-        let coreml = ExecutionProvider::CoreML(CoreMLExecutionProviderOptions::default());
-        if coreml.is_available() {
-            eps.push(coreml);
-        }
+    let session = Arc::new(build_session(model_path)?);
+    let (tx, rx) = mpsc::channel::<Job>(queue_cap);
+    let rx = Arc::new(Mutex::new(rx));
+
+    for _ in 0..workers.max(1) {
+        let (session, rx) = (session.clone(), rx.clone());
+        tokio::spawn(async move {
+            loop {
+                let Some(Job { image, tx }) = rx.lock().await.recv().await else { break };
+                let session = session.clone();
+                let out = tokio::task::spawn_blocking(move || infer_clip(&session, &image))
+                    .await
+                    .unwrap_or(Err(ApiError::Internal));
+                let _ = tx.send(out);
+            }
+        });
     }
 
-    eps.push(ExecutionProvider::CPU(
-        CPUExecutionProviderOptions::default(),
-    ));
+    Ok(Engine { tx })
+}
+
+pub fn build_session(model_path: &str) -> anyhow::Result<Session> {
+    // Accelerators the build enables and the machine has, then the CPU
+    let eps: Vec<ExecutionProvider> = [
+        #[cfg(feature = "accel-cuda")]
+        ExecutionProvider::CUDA(CUDAExecutionProviderOptions::default()),
+        #[cfg(feature = "accel-coreml")]
+        ExecutionProvider::CoreML(CoreMLExecutionProviderOptions::default()),
+        ExecutionProvider::CPU(CPUExecutionProviderOptions::default()),
+    ]
+    .into_iter()
+    .filter(|ep| matches!(ep, ExecutionProvider::CPU(_)) || ep.is_available())
+    .collect();
 
     let environment = Environment::builder().build()?.into_arc();
 
@@ -92,23 +96,23 @@ fn preprocess_clip(img: &DynamicImage) -> Vec<f32> {
   );
 
   let mean = [0.48145466f32, 0.4578275, 0.40821073];
-  let std = [0.26862954f32, 0.26130258, 0.27577711];
+  let std = [0.26862954f32, 0.261_302_6, 0.275_777_1];
 
   let mut chw = vec![0f32; 3 * 224 * 224];
   for y in 0..224 {
     for x in 0..224 {
       let p = resized.get_pixel(x, y).0;
       let i = (y as usize) * 224 + x as usize;
-      chw[0 * 224 * 224 + i] = (p[0] as f32 / 255.0 - mean[0]) / std[0];
-      chw[1 * 224 * 224 + i] = (p[1] as f32 / 255.0 - mean[1]) / std[1];
-      chw[2 * 224 * 224 + i] = (p[2] as f32 / 255.0 - mean[2]) / std[2];
+      for c in 0..3 {
+        chw[c * 224 * 224 + i] = (p[c] as f32 / 255.0 - mean[c]) / std[c];
+      }
 
     }
   }
   chw
 }
 
-async fn infer_clip(session: &Session, img: &DynamicImage) -> Result<EngineOutput, ApiError> {
+fn infer_clip(session: &Session, img: &DynamicImage) -> Result<EngineOutput, ApiError> {
   let chw = preprocess_clip(img);
 
   let arr = ndarray::Array::from_shape_vec((1, 3, 224, 224), chw)
@@ -135,5 +139,5 @@ async fn infer_clip(session: &Session, img: &DynamicImage) -> Result<EngineOutpu
   let n = (v.iter().map(|x| x * x).sum::<f32>()).sqrt().max(1e-12);
   for x in &mut v { *x /= n; }
 
-  Ok(EngineOutput { embed_dim: v.len(), embedding: v })
+  Ok(EngineOutput { embedding: v, caption: String::new(), tags: Vec::new() })
 }
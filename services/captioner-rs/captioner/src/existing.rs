@@ -0,0 +1,164 @@
+// Merchant-written alt text sent as `existing_alt`: keep it, improve it, or replace it with the
+// generated one. Lint errors (filenames, empty text, a different product or color than the
+// image shows), a low lint score or no product named mean `replace`. Otherwise the merchant's
+// wording stays: text fixes from the lint and attributes the pipeline found but the text
+// lacks are added (`improve`), and text that needs neither is kept as written (`keep`).
+
+use serde::Serialize;
+
+use crate::compose::Slot;
+use crate::lint::{self, Severity};
+use crate::matching;
+use crate::pipeline::CaptionCtx;
+use crate::taxonomy::Taxonomy;
+
+// Below this lint score the merchant's text isn't worth building on.
+const REPLACE_BELOW: u8 = 50;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Keep,
+    Improve,
+    Replace,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ExistingAlt {
+    pub decision: Decision,
+    pub reasons: Vec<String>,
+    // Lint score of the merchant's text
+    pub score: u8,
+}
+
+// Decide on `existing` given the generated alt text in `ctx`, returning the alt text to use.
+pub fn decide(tax: &Taxonomy, ctx: &CaptionCtx, existing: &str) -> (String, ExistingAlt) {
+    let reference = lint::Reference::from_ctx(ctx);
+    let report = lint::check(existing, &ctx.policy, tax, Some(&reference));
+    let replace = |why: String| (ctx.alt.clone(), ExistingAlt { decision: Decision::Replace, reasons: vec![why], score: report.score });
+
+    let fatal = report.issues.iter().find(|i| i.severity == Severity::Error || i.code == "color_mismatch");
+    if let Some(i) = fatal {
+        return replace(i.message.clone());
+    }
+    if report.score < REPLACE_BELOW {
+        return replace(format!("lint score {} is below {REPLACE_BELOW}", report.score));
+    }
+    let Some(category) = ctx.refine.as_ref().and_then(|r| r.category.as_deref()).and_then(|c| tax.category(c))
+    else {
+        // Nothing to compare against or add; the merchant's text (with text fixes) stands.
+        let reasons: Vec<String> = report.issues.iter().map(|i| i.message.clone()).collect();
+        let text = report.suggested_alt.clone().unwrap_or_else(|| existing.trim().to_string());
+        let decision = if reasons.is_empty() { Decision::Keep } else { Decision::Improve };
+        return (text, ExistingAlt { decision, reasons, score: report.score });
+    };
+    if report.issues.iter().any(|i| i.code == "missing_subject") {
+        return replace(format!("doesn't name the product ({})", category.name));
+    }
+
+    let mut reasons: Vec<String> = report.issues.iter().map(|i| i.message.clone()).collect();
+    let mut text = report.suggested_alt.clone().unwrap_or_else(|| existing.trim().to_string());
+    let attributes = ctx.refine.as_ref().map_or(&[][..], |r| &r.attributes);
+    for choice in attributes {
+        let Some(attr) = tax.attribute(&choice.attribute) else { continue };
+        let value = attr.form_of(&choice.value);
+        if matching::Tokens::new(&text).contains(value) {
+            continue;
+        }
+        let added = match attr.slot_of(&choice.value) {
+            Some(slot) => insert_modifier(tax, category, &text, slot, value),
+            None => Some(append_detail(&text, value)),
+        };
+        if let Some(t) = added {
+            reasons.push(format!("adds {} \"{value}\"", choice.attribute));
+            text = t;
+        }
+    }
+    let decision = if reasons.is_empty() { Decision::Keep } else { Decision::Improve };
+    (text, ExistingAlt { decision, reasons, score: report.score })
+}
+
+// Put `value` among the modifiers right before the category noun, in English slot order:
+// "Leather shoe" + black -> "Black leather shoe".
+fn insert_modifier(tax: &Taxonomy, category: &crate::taxonomy::Category, text: &str, slot: Slot, value: &str) -> Option<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let bare = |w: &str| w.trim_matches(|c: char| !c.is_alphanumeric() && c != '-').to_string();
+    let noun = (0..words.len()).find(|&i| {
+        (1..=3).filter(|n| i + n <= words.len()).any(|n| {
+            let candidate = words[i..i + n].iter().map(|w| bare(w)).collect::<Vec<_>>().join(" ");
+            category.names().any(|name| matching::same_phrase(&candidate, name))
+        })
+    })?;
+    // Walk back over words the taxonomy knows as modifiers of this category.
+    let slot_of = |w: &str| {
+        tax.attributes_of(category)
+            .find_map(|(_, a)| a.values().into_iter().find(|v| matching::same_phrase(w, v)).and_then(|v| a.slot_of(v)))
+    };
+    let mut at = noun;
+    while at > 0 && let Some(s) = slot_of(&bare(words[at - 1])) {
+        if s <= slot {
+            break;
+        }
+        at -= 1;
+    }
+    let mut out: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+    out.insert(at, value.to_string());
+    if at == 0 && out.len() > 1 {
+        out[1] = lowercase_word(&out[1]);
+    }
+    Some(crate::compose::sentence_case(&out.join(" ")))
+}
+
+// "Mug with logo" + handle -> "Mug with logo and handle".
+fn append_detail(text: &str, detail: &str) -> String {
+    let text = text.trim_end_matches(['.', ' ']);
+    if matching::Tokens::new(text).contains("with") {
+        format!("{text} and {detail}")
+    } else {
+        format!("{text} with {detail}")
+    }
+}
+
+// Lowercase a sentence-cased first word, leaving acronyms and brands alone.
+fn lowercase_word(w: &str) -> String {
+    let mut chars = w.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), Some(d)) if c.is_uppercase() && d.is_lowercase() => c.to_lowercase().chain(w[c.len_utf8()..].chars()).collect(),
+        _ => w.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt_policy::{AltTextPolicy, Limit};
+    use crate::trace::{RefineTrace, Source};
+    use crate::CaptionReq;
+
+    fn ctx(alt: &str, category: &str, attrs: &[(&str, &str)]) -> CaptionCtx {
+        let mut ctx = CaptionCtx::new(CaptionReq::default(), AltTextPolicy::new(125, Limit::Soft));
+        ctx.alt = alt.into();
+        let mut tr = RefineTrace { category: Some(category.into()), ..Default::default() };
+        for (a, v) in attrs {
+            tr.choose(a, v, Source::Tag);
+        }
+        ctx.refine = Some(tr);
+        ctx
+    }
+
+    #[test]
+    fn keeps_improves_or_replaces() {
+        let tax = Taxonomy::builtin();
+        let c = ctx("Black leather shoe with buckle", "shoe", &[("color", "black"), ("material", "leather"), ("detail", "buckle")]);
+        let decide = |existing: &str| {
+            let (alt, d) = decide(&tax, &c, existing);
+            (alt, d.decision)
+        };
+        assert_eq!(decide("Black leather shoe with a buckle"), ("Black leather shoe with a buckle".into(), Decision::Keep));
+        assert_eq!(decide("Leather shoe with a buckle"), ("Black leather shoe with a buckle".into(), Decision::Improve));
+        assert_eq!(decide("Image of our black shoe"), ("Our black leather shoe with buckle".into(), Decision::Improve));
+        assert_eq!(decide("IMG_2031.jpg"), ("Black leather shoe with buckle".into(), Decision::Replace));
+        assert_eq!(decide("Brown leather shoe with buckle").1, Decision::Replace);
+        assert_eq!(decide("Summer vibes").1, Decision::Replace);
+    }
+}
@@ -0,0 +1,227 @@
+// Fetching of user-supplied image URLs. Uses its own HTTP client so the URL policy
+// (resolver + redirect checks) applies only to untrusted targets and not to our own
+// remote inference endpoints, which typically live on private addresses.
+// `file://` and `s3://` URLs are dispatched to the sources in `sources.rs`.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use captioner::ApiError;
+use reqwest::{Client, Url};
+use tokio::time::Duration;
+
+use crate::cdn::CdnRewriter;
+use crate::sources::{FileSource, S3Source};
+use crate::url_policy::{self, PolicyResolver, UrlPolicy};
+
+pub struct Fetcher {
+    http: Client,
+    policy: Arc<UrlPolicy>,
+    cdn: CdnRewriter,
+    files: FileSource,
+    s3: Option<S3Source>,
+}
+
+impl Fetcher {
+    pub fn new(policy: UrlPolicy) -> reqwest::Result<Self> {
+        let policy = Arc::new(policy);
+        let http = Client::builder()
+            .connect_timeout(Duration::from_secs(3))
+            .timeout(Duration::from_secs(10))
+            .tcp_keepalive(Duration::from_secs(30))
+            .pool_idle_timeout(Duration::from_secs(30))
+            .pool_max_idle_per_host(8)
+            .no_proxy()
+            .dns_resolver(Arc::new(PolicyResolver::new(policy.clone())))
+            .redirect(url_policy::redirect_policy(policy.clone()))
+            .build()?;
+        Ok(Fetcher { http, policy, cdn: CdnRewriter::default(), files: FileSource::default(), s3: None })
+    }
+
+    pub fn with_cdn(mut self, cdn: CdnRewriter) -> Self {
+        self.cdn = cdn;
+        self
+    }
+
+    pub fn with_files(mut self, files: FileSource) -> Self {
+        self.files = files;
+        self
+    }
+
+    pub fn with_s3(mut self, s3: Option<S3Source>) -> Self {
+        self.s3 = s3;
+        self
+    }
+
+    pub async fn fetch_bytes(&self, url: &str) -> Result<Bytes, ApiError> {
+        let parsed = Url::parse(url).map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid image_url")))?;
+        match parsed.scheme() {
+            "file" => return self.files.fetch(&parsed).await,
+            "s3" => {
+                return match &self.s3 {
+                    Some(s3) => s3.fetch(&parsed).await,
+                    None => Err(ApiError::BadRequest(Cow::Borrowed("s3:// sources not enabled"))),
+                };
+            }
+            _ => {}
+        }
+        if let Err(v) = self.policy.check_url(&parsed) {
+            tracing::warn!(url = %url, err = %v, "image url blocked by url policy");
+            return Err(blocked());
+        }
+        // Prefer a right-sized CDN rendition; the original is always the fallback.
+        if let Some(small) = self.cdn.rewrite(&parsed) {
+            match self.get(small).await {
+                Ok(b) => return Ok(b),
+                Err(e) => tracing::debug!(url = %url, err = %e, "cdn rendition failed; fetching original"),
+            }
+        }
+        self.get(parsed).await
+    }
+
+    async fn get(&self, url: Url) -> Result<Bytes, ApiError> {
+        let resp = self.http.get(url).send().await.map_err(|e| {
+            if url_policy::find_violation(&e).is_some() {
+                blocked()
+            } else {
+                ApiError::BadRequest(Cow::Borrowed("image url not fetchable"))
+            }
+        })?;
+        if !resp.status().is_success() {
+            return Err(ApiError::BadRequest(Cow::Borrowed(
+                "image url not fetchable",
+            )));
+        }
+        resp.bytes()
+            .await
+            .map_err(|_| ApiError::BadRequest(Cow::Borrowed("read body failed")))
+    }
+}
+
+fn blocked() -> ApiError {
+    ApiError::BadRequest(Cow::Borrowed("image url blocked by policy"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cdn::CdnRule;
+    use axum::{Router, response::Redirect, routing::get};
+
+    // Serve a tiny app on an ephemeral loopback port and return its base URL.
+    async fn serve() -> String {
+        let app = Router::new()
+            .route("/img.jpg", get(|| async { &b"\xFF\xD8\xFFjpeg"[..] }))
+            .route("/to-metadata", get(|| async { Redirect::temporary("http://169.254.169.254/latest/meta-data/") }))
+            .route("/to-private", get(|| async { Redirect::temporary("http://10.0.0.7/admin") }))
+            .route("/to-self", get(|| async { Redirect::temporary("/img.jpg") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    // Records the query string of every hit so tests can see which rendition was requested.
+    async fn serve_cdn(fail_resized: bool) -> (String, Arc<std::sync::Mutex<Vec<String>>>) {
+        use axum::{extract::RawQuery, http::StatusCode};
+        let hits = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = hits.clone();
+        let app = Router::new().route(
+            "/s/files/shoe.jpg",
+            get(move |RawQuery(q): RawQuery| {
+                let seen = seen.clone();
+                async move {
+                    let q = q.unwrap_or_default();
+                    seen.lock().unwrap().push(q.clone());
+                    if q.contains("width=") {
+                        if fail_resized { (StatusCode::NOT_FOUND, "nope") } else { (StatusCode::OK, "small") }
+                    } else {
+                        (StatusCode::OK, "original")
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{addr}"), hits)
+    }
+
+    fn cdn_fetcher() -> Fetcher {
+        let rule = CdnRule { host: "127.0.0.1".into(), path_prefix: Some("/s/files/".into()), width_param: "width".into(), height_param: "height".into() };
+        loopback_ok().with_cdn(CdnRewriter::shopify(224).with_rule(rule))
+    }
+
+    fn loopback_ok() -> Fetcher {
+        Fetcher::new(UrlPolicy { allow_loopback: true, ..Default::default() }).unwrap()
+    }
+
+    fn err_msg(e: ApiError) -> String {
+        match e {
+            ApiError::BadRequest(m) => m.into_owned(),
+            ApiError::Internal => "internal".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn blocks_loopback_by_default() {
+        let base = serve().await;
+        let f = Fetcher::new(UrlPolicy::default()).unwrap();
+        let e = f.fetch_bytes(&format!("{base}/img.jpg")).await.unwrap_err();
+        assert_eq!(err_msg(e), "image url blocked by policy");
+    }
+
+    #[tokio::test]
+    async fn blocks_names_resolving_to_loopback() {
+        let base = serve().await;
+        let port = base.rsplit(':').next().unwrap();
+        let f = Fetcher::new(UrlPolicy::default()).unwrap();
+        let e = f.fetch_bytes(&format!("http://localhost:{port}/img.jpg")).await.unwrap_err();
+        assert_eq!(err_msg(e), "image url blocked by policy");
+    }
+
+    #[tokio::test]
+    async fn fetches_when_allowed_and_follows_safe_redirects() {
+        let base = serve().await;
+        let f = loopback_ok();
+        let b = f.fetch_bytes(&format!("{base}/img.jpg")).await.unwrap();
+        assert_eq!(&b[..3], &[0xFF, 0xD8, 0xFF]);
+        let b = f.fetch_bytes(&format!("{base}/to-self")).await.unwrap();
+        assert_eq!(&b[..3], &[0xFF, 0xD8, 0xFF]);
+    }
+
+    #[tokio::test]
+    async fn blocks_redirects_into_internal_ranges() {
+        let base = serve().await;
+        let f = loopback_ok();
+        for path in ["to-metadata", "to-private"] {
+            let e = f.fetch_bytes(&format!("{base}/{path}")).await.unwrap_err();
+            assert_eq!(err_msg(e), "image url blocked by policy", "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn allowlist_rejects_other_hosts() {
+        let base = serve().await;
+        let f = Fetcher::new(UrlPolicy { allow_hosts: vec!["cdn.shopify.com".into()], allow_loopback: true }).unwrap();
+        let e = f.fetch_bytes(&format!("{base}/img.jpg")).await.unwrap_err();
+        assert_eq!(err_msg(e), "image url blocked by policy");
+    }
+
+    #[tokio::test]
+    async fn requests_right_sized_cdn_rendition() {
+        let (base, hits) = serve_cdn(false).await;
+        let b = cdn_fetcher().fetch_bytes(&format!("{base}/s/files/shoe.jpg")).await.unwrap();
+        assert_eq!(&b[..], b"small");
+        assert_eq!(*hits.lock().unwrap(), vec!["width=224&height=224".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_original_when_rendition_fails() {
+        let (base, hits) = serve_cdn(true).await;
+        let b = cdn_fetcher().fetch_bytes(&format!("{base}/s/files/shoe.jpg")).await.unwrap();
+        assert_eq!(&b[..], b"original");
+        assert_eq!(hits.lock().unwrap().len(), 2);
+    }
+}
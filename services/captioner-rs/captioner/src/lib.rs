@@ -1,5 +1,7 @@
 pub mod engine;
 
+use std::borrow::Cow;
+
 #[cfg(feature = "turbo-ffi")]
 use std::{
     cell::RefCell,
@@ -12,9 +14,11 @@ use bytes::Bytes;
 use image::DynamicImage;
 use serde::Serialize;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum ApiError {
-    BadRequest(&'static str),
+    #[error("{0}")]
+    BadRequest(Cow<'static, str>),
+    #[error("internal error")]
     Internal,
 }
 
@@ -22,7 +26,7 @@ impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
         match self {
             ApiError::BadRequest(msg) => {
-                (StatusCode::BAD_REQUEST, Json(ErrBody { error: msg.into() })).into_response()
+                (StatusCode::BAD_REQUEST, Json(ErrBody { error: msg.into_owned() })).into_response()
             }
             ApiError::Internal => (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -52,10 +56,12 @@ impl Drop for TJHandle {
     }
 }
 
+#[cfg(feature = "turbo-ffi")]
 thread_local! {
     static TJ: RefCell<Option<TJHandle>> = const {RefCell::new(None)};
 }
 
+#[cfg(feature = "turbo-ffi")]
 fn with_tj<R>(f: impl FnOnce(*mut c_void) -> Result<R, ApiError>) -> Result<R, ApiError> {
     TJ.with(|slot| {
         if slot.borrow().is_none() {
@@ -94,7 +100,7 @@ fn get_size(
         )
     };
     if result > 0 || out_w <= 0 || out_h <= 0 {
-        return Err(ApiError::BadRequest("invalid jpeg"));
+        return Err(ApiError::BadRequest(Cow::Borrowed("invalid jpeg")));
     }
 
     // println!("\nout_w: {} out_h: {}", &out_w, &out_h);
@@ -105,7 +111,7 @@ fn get_size(
 #[cfg(feature = "turbo-ffi")]
 pub async fn decode(jpeg_buf: &Bytes) -> Result<DynamicImage, ApiError> {
     if !is_jpeg(jpeg_buf.as_ref()) {
-        return Err(ApiError::BadRequest("invalid jpeg"));
+        return Err(ApiError::BadRequest(Cow::Borrowed("invalid jpeg")));
     }
     let b = jpeg_buf.clone();
     let res = tokio::task::spawn_blocking(move || {
@@ -129,17 +135,17 @@ pub async fn decode(jpeg_buf: &Bytes) -> Result<DynamicImage, ApiError> {
                 )
             };
             if result != 0 {
-                return Err(ApiError::BadRequest("jpeg decompress failed"));
+                return Err(ApiError::BadRequest(Cow::Borrowed("jpeg decompress failed")));
             }
 
             let rgb = image::RgbImage::from_vec(w as u32, h as u32, dst_rgb)
-                .ok_or::<ApiError>(ApiError::BadRequest("invalid jpeg"))?;
+                .ok_or::<ApiError>(ApiError::BadRequest(Cow::Borrowed("invalid jpeg")))?;
             Ok(DynamicImage::ImageRgb8(rgb))
         });
         img
     })
     .await
-    .map_err(|_| ApiError::BadRequest("decode failed"))?;
+    .map_err(|_| ApiError::BadRequest(Cow::Borrowed("decode failed")))?;
     res
 }
 
@@ -175,7 +181,7 @@ pub async fn decode_image(bytes: Bytes) -> Result<DynamicImage, ApiError> {
         let b = bytes.clone();
         let res = tokio::task::spawn_blocking(move || {
             let rgb: image::RgbImage = turbojpeg::decompress_image(&b)
-                .map_err(|_| ApiError::BadRequest("invalid jpeg"))?;
+                .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid jpeg")))?;
             Ok::<DynamicImage, ApiError>(DynamicImage::ImageRgb8(rgb))
         })
         .await
@@ -186,5 +192,13 @@ pub async fn decode_image(bytes: Bytes) -> Result<DynamicImage, ApiError> {
     tokio::task::spawn_blocking(move || image::load_from_memory(&bytes))
         .await
         .map_err(|_| ApiError::Internal)?
-        .map_err(|_| ApiError::BadRequest("invalid image data"))
+        .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid image data")))
+}
+
+#[cfg(not(feature = "turbo"))]
+pub async fn decode_image(bytes: Bytes) -> Result<DynamicImage, ApiError> {
+    tokio::task::spawn_blocking(move || image::load_from_memory(&bytes))
+        .await
+        .map_err(|_| ApiError::Internal)?
+        .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid image data")))
 }
@@ -0,0 +1,278 @@
+// `POST /v1/lint`: audit alt text a merchant already has against WCAG and Shopify guidance.
+// Text checks (length, "image of" prefixes, filenames, repeated words, all caps, no product
+// named) need only the string; with an image, the caption pipeline also runs and the alt text
+// is compared against what it found. Each issue carries a severity and, where one exists, a
+// suggested fix; `suggested_alt` applies them all.
+
+use std::sync::{Arc, atomic::Ordering};
+
+use axum::{
+    Json,
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::alt_policy::AltTextPolicy;
+use crate::pipeline::CaptionCtx;
+use crate::taxonomy::Taxonomy;
+use crate::{AppState, CaptionReq, compose, matching, seo};
+
+// Score deductions per issue, out of 100.
+const ERROR_COST: u8 = 40;
+const WARNING_COST: u8 = 15;
+const INFO_COST: u8 = 5;
+
+// Shorter than this can't describe a product ("Shoe").
+const MIN_LEN: usize = 10;
+
+// A content word used this often is keyword stuffing.
+const MAX_REPEATS: usize = 2;
+
+// Prefixes merchants write that clean_caption's model prefixes don't cover.
+const MERCHANT_PREFIXES: &[&str] = &["image of ", "photo of ", "picture of ", "graphic of "];
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "gif", "heic", "avif", "tif", "tiff", "bmp"];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Issue {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub score: u8,
+    pub issues: Vec<Issue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_alt: Option<String>,
+}
+
+// What the caption pipeline saw in the image.
+pub struct Reference<'a> {
+    pub alt: &'a str,
+    pub category: Option<&'a str>,
+    pub color: Option<&'a str>,
+}
+
+impl<'a> Reference<'a> {
+    pub fn from_ctx(ctx: &'a CaptionCtx) -> Self {
+        let refine = ctx.refine.as_ref();
+        Reference {
+            alt: &ctx.alt,
+            category: refine.and_then(|r| r.category.as_deref()),
+            color: refine.and_then(|r| r.attributes.iter().find(|a| a.attribute == "color")).map(|a| a.value.as_str()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LintReq {
+    alt_text: String,
+    // Optional image and product context, as for /v1/caption
+    #[serde(flatten)]
+    caption: CaptionReq,
+}
+
+pub async fn lint(State(state): State<Arc<AppState>>, Json(mut inp): Json<LintReq>) -> Response {
+    state.request_count.fetch_add(1, Ordering::Relaxed);
+    let policy = state.alt_policies.resolve(inp.caption.shop.as_deref(), inp.caption.alt_policy.as_ref());
+    // As the caption route does: a `data:` image_url becomes the image bytes.
+    if let Err(e) = crate::upload::resolve_data_uri(&mut inp.caption) {
+        return e.into_response();
+    }
+    if inp.caption.image_url.trim().is_empty() && inp.caption.image_bytes.is_none() {
+        return Json(check(&inp.alt_text, &policy, &state.taxonomy, None)).into_response();
+    }
+    inp.caption.debug = false;
+    inp.caption.candidates = None;
+    inp.caption.long_description = false;
+    match state.pipeline.run(&state, inp.caption).await {
+        Ok(ctx) => Json(check(&inp.alt_text, &policy, &state.taxonomy, Some(&Reference::from_ctx(&ctx)))).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub fn check(alt: &str, policy: &AltTextPolicy, tax: &Taxonomy, reference: Option<&Reference>) -> Report {
+    let mut issues = Vec::new();
+    let mut issue = |code, severity, message: &str, suggestion: Option<String>| {
+        issues.push(Issue { code, severity, message: message.to_string(), suggestion });
+    };
+    let generated = reference.map(|r| r.alt.to_string());
+    let text = alt.trim();
+    if text.is_empty() {
+        issue("empty", Severity::Error, "alt text is empty; screen readers skip the image or read its filename", generated);
+        return report(issues, alt, policy);
+    }
+
+    if is_filename(text) {
+        issue("filename", Severity::Error, "alt text is a filename, not a description", generated.clone());
+    }
+    let lower = text.to_lowercase();
+    if crate::CAPTION_PREFIXES.iter().chain(MERCHANT_PREFIXES).any(|p| lower.starts_with(p)) {
+        issue("redundant_prefix", Severity::Warning, "screen readers already announce an image; drop \"image of\"", Some(strip_prefix(text)));
+    }
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() >= 4 && letters.iter().all(|c| c.is_uppercase()) {
+        issue("all_caps", Severity::Warning, "all caps is read letter by letter by some screen readers", Some(compose::sentence_case(&lower)));
+    }
+    if repeats(text) > MAX_REPEATS {
+        issue("repeated_words", Severity::Warning, "the same word repeats; looks like keyword stuffing", Some(seo::limit_repeats(text, 1)));
+    }
+    let len = text.chars().count();
+    if len > policy.max_len {
+        issue("too_long", Severity::Warning, &format!("{len} characters; the limit is {}", policy.max_len), Some(policy.apply(text)));
+    } else if len < MIN_LEN && !is_filename(text) {
+        issue("too_short", Severity::Warning, "too short to describe the product", generated.clone());
+    }
+
+    let category = tax.category_in_text(text).map(|c| c.name.as_str());
+    match (category, reference) {
+        (Some(mine), Some(Reference { category: Some(seen), .. })) if !matching::same_phrase(mine, seen) => issue(
+            "image_mismatch",
+            Severity::Error,
+            &format!("describes a {mine}, but the image shows a {seen}"),
+            generated.clone(),
+        ),
+        (None, _) if !is_filename(text) => issue("missing_subject", Severity::Info, "doesn't name the product", generated.clone()),
+        _ => {}
+    }
+    if let Some(seen) = reference.and_then(|r| r.color)
+        && let Some(colors) = tax.attribute("color")
+        && let Some(mine) = crate::pick_from_text(&colors.values(), text)
+        && !matching::same_phrase(mine, seen)
+    {
+        issue("color_mismatch", Severity::Warning, &format!("says {mine}, but the product looks {seen}"), generated.clone());
+    }
+    report(issues, alt, policy)
+}
+
+fn report(mut issues: Vec<Issue>, alt: &str, policy: &AltTextPolicy) -> Report {
+    issues.sort_by_key(|i| i.severity);
+    let cost: u32 = issues
+        .iter()
+        .map(|i| match i.severity {
+            Severity::Error => ERROR_COST,
+            Severity::Warning => WARNING_COST,
+            Severity::Info => INFO_COST,
+        } as u32)
+        .sum();
+    let suggested_alt = suggest(&issues, alt, policy);
+    Report { score: 100u32.saturating_sub(cost) as u8, issues, suggested_alt }
+}
+
+// Errors are fixed by the generated alt text (when there is one); otherwise text fixes apply in order.
+fn suggest(issues: &[Issue], alt: &str, policy: &AltTextPolicy) -> Option<String> {
+    if issues.is_empty() {
+        return None;
+    }
+    if let Some(i) = issues.iter().find(|i| i.severity == Severity::Error) {
+        return i.suggestion.clone();
+    }
+    let mut text = alt.trim().to_string();
+    for i in issues {
+        text = match i.code {
+            "redundant_prefix" => strip_prefix(&text),
+            "all_caps" => compose::sentence_case(&text.to_lowercase()),
+            "repeated_words" => seo::limit_repeats(&text, 1),
+            "too_long" => policy.apply(&text),
+            _ => text,
+        };
+    }
+    Some(text).filter(|t| t != alt.trim())
+}
+
+fn strip_prefix(text: &str) -> String {
+    let lower = text.to_lowercase();
+    let rest = crate::CAPTION_PREFIXES
+        .iter()
+        .chain(MERCHANT_PREFIXES)
+        .find(|p| lower.starts_with(*p))
+        .map_or(text, |p| &text[p.len()..]);
+    compose::sentence_case(rest.trim())
+}
+
+// "IMG_2031.jpg", "DSC00012", "product-photo-3.png", "hero_banner_v2".
+fn is_filename(text: &str) -> bool {
+    if text.contains(char::is_whitespace) {
+        return false;
+    }
+    let lower = text.to_lowercase();
+    let has_extension = lower.rsplit_once('.').is_some_and(|(stem, ext)| !stem.is_empty() && IMAGE_EXTENSIONS.contains(&ext));
+    let camera = ["img_", "img-", "dsc", "dscn", "pxl_", "photo_"].iter().any(|p| lower.starts_with(p)) && lower.chars().any(|c| c.is_ascii_digit());
+    let joined = lower.contains(['_', '-']) && lower.chars().any(|c| c.is_ascii_digit());
+    has_extension || camera || joined
+}
+
+// Most uses of any one content word.
+fn repeats(text: &str) -> usize {
+    let words = seo::content_words(text);
+    words.iter().map(|w| words.iter().filter(|x| *x == w).count()).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt_policy::Limit;
+
+    #[tokio::test]
+    async fn lints_against_a_data_uri_image() {
+        use base64::Engine as _;
+        let state = crate::tests::dummy_state_with(crate::pipeline::CaptionPipeline::standard());
+        let uri = format!("data:image/jpeg;base64,{}", base64::engine::general_purpose::STANDARD.encode(crate::tests::SAMPLE_JPG));
+        let req = serde_json::json!({"alt_text": "", "image_url": uri, "product_title": "Red Leather Shoe"});
+        let resp = lint(State(state.clone()), Json(serde_json::from_value(req).unwrap())).await;
+        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+        let v: serde_json::Value = serde_json::from_slice(&axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(v["issues"][0]["suggestion"], "Red leather shoe");
+
+        let req = serde_json::json!({"alt_text": "Shoe", "image_url": "data:text/plain;base64,aGk="});
+        let resp = lint(State(state), Json(serde_json::from_value(req).unwrap())).await;
+        assert_eq!(resp.status(), axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    fn codes(alt: &str, reference: Option<&Reference>) -> Vec<&'static str> {
+        let tax = Taxonomy::builtin();
+        check(alt, &AltTextPolicy::new(125, Limit::Soft), &tax, reference).issues.iter().map(|i| i.code).collect()
+    }
+
+    #[test]
+    fn flags_common_merchant_alt_text() {
+        assert_eq!(codes("IMG_2031.jpg", None), ["filename"]);
+        assert_eq!(codes("Image of a black leather shoe", None), ["redundant_prefix"]);
+        assert_eq!(codes("BLACK LEATHER SHOE", None), ["all_caps"]);
+        assert_eq!(codes("Shoe shoes leather shoe oxford shoe", None), ["repeated_words"]);
+        assert_eq!(codes("Summer vibes", None), ["missing_subject"]);
+        assert_eq!(codes("", None), ["empty"]);
+        assert!(codes("Black leather shoe with buckle", None).is_empty());
+
+        let seen = Reference { alt: "Red leather boot", category: Some("boot"), color: Some("red") };
+        assert_eq!(codes("Black leather shoe", Some(&seen)), ["image_mismatch", "color_mismatch"]);
+    }
+
+    #[test]
+    fn scores_and_suggests_fixes() {
+        let tax = Taxonomy::builtin();
+        let policy = AltTextPolicy::new(125, Limit::Soft);
+        let r = check("IMAGE OF A BLACK LEATHER SHOE", &policy, &tax, None);
+        assert_eq!(r.score, 100 - 2 * WARNING_COST);
+        assert_eq!(r.suggested_alt.as_deref(), Some("A black leather shoe"));
+
+        let seen = Reference { alt: "Red leather shoe", category: Some("shoe"), color: Some("red") };
+        let r = check("DSC00012", &policy, &tax, Some(&seen));
+        assert_eq!(r.issues[0].severity, Severity::Error);
+        assert_eq!(r.suggested_alt.as_deref(), Some("Red leather shoe"));
+        assert!(check("Red leather shoe", &policy, &tax, Some(&seen)).suggested_alt.is_none());
+    }
+}
@@ -0,0 +1,349 @@
+// Alt text in French, German and Spanish (`"locale"`, or detected from the product title).
+// refine_alt works in English; this module re-says its result (category, attributes,
+// details) from the vocabularies in locales.toml, placing adjectives before or after the noun
+// and making them agree with its gender and number. Anything a locale has no words for is left
+// out, and a category it can't name keeps the whole alt text in English.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::matching::Tokens;
+
+const BUILTIN: &str = include_str!("../locales.toml");
+
+// Languages a shop can publish in Shopify Markets.
+pub const MAX_LOCALES: usize = 20;
+
+// English function words, for telling an English title from a foreign one.
+const EN_STOPWORDS: &[&str] = &["the", "a", "an", "and", "with", "for", "of", "in", "on", "by", "to"];
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    Before,
+    After,
+}
+
+// How adjectives agree with the noun.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Inflection {
+    // As written, unless the word lists its forms
+    #[default]
+    None,
+    // -e for feminine, -s for plural
+    French,
+    // -o becomes -a for feminine; -s or -es for plural
+    Spanish,
+    // Strong endings on the stem: -er, -e, -es, plural -e
+    German,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+enum Gender {
+    #[serde(rename = "m")]
+    Masculine,
+    #[serde(rename = "f")]
+    Feminine,
+    #[serde(rename = "n")]
+    Neuter,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Noun {
+    word: String,
+    gender: Gender,
+    #[serde(default)]
+    plural: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Word {
+    adj: Option<String>,
+    f: Option<String>,
+    pl: Option<String>,
+    fpl: Option<String>,
+    #[serde(default)]
+    invariable: bool,
+    phrase: Option<String>,
+    noun: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Lang {
+    order: Order,
+    #[serde(default)]
+    inflection: Inflection,
+    with: String,
+    and: String,
+    template: String,
+    measure: String,
+    #[serde(default)]
+    prefixes: Vec<String>,
+    #[serde(default)]
+    stopwords: Vec<String>,
+    nouns: HashMap<String, Noun>,
+    #[serde(default)]
+    words: HashMap<String, Word>,
+}
+
+#[derive(Debug)]
+pub struct Locales {
+    langs: HashMap<String, Lang>,
+}
+
+// A refined attribute value as the localizer needs it.
+pub enum Value<'a> {
+    // Taxonomy vocabulary value, looked up in `words`
+    Word(&'a str),
+    // A number and unit ("750 ml"), said the same in every language
+    Measure(&'a str),
+}
+
+// One TranslationInput for Shopify's translationsRegister mutation.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Translation {
+    pub locale: String,
+    pub key: &'static str,
+    pub value: String,
+    pub translatable_content_digest: String,
+}
+
+// Variables for translationsRegister(resourceId, translations), sendable as they are.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationsRegister {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_id: Option<String>,
+    pub translations: Vec<Translation>,
+}
+
+impl TranslationsRegister {
+    // `alt` in other languages; Shopify checks `digest` against the alt text it holds.
+    pub fn alt(resource_id: Option<String>, digest: String, translated: Vec<(String, String)>) -> Self {
+        let translations = translated
+            .into_iter()
+            .map(|(locale, value)| Translation { locale, key: "alt", value, translatable_content_digest: digest.clone() })
+            .collect();
+        TranslationsRegister { resource_id, translations }
+    }
+}
+
+// translatableContentDigest: hex SHA-256 of the original text.
+pub fn content_digest(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+impl Locales {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let langs: HashMap<String, Lang> = toml::from_str(src).map_err(|e| e.to_string())?;
+        for (code, lang) in &langs {
+            if code.len() != 2 || code == "en" {
+                return Err(format!("locale {code:?}: expected a two-letter code other than \"en\""));
+            }
+            if !lang.template.contains("{title}") || !lang.measure.contains("{}") {
+                return Err(format!("locale {code:?}: template needs {{title}} and measure needs {{}}"));
+            }
+            for (value, w) in &lang.words {
+                let kinds = [w.adj.is_some(), w.phrase.is_some(), w.noun.is_some()].iter().filter(|k| **k).count();
+                if kinds != 1 {
+                    return Err(format!("locale {code:?}: {value:?} needs exactly one of adj, phrase or noun"));
+                }
+            }
+        }
+        Ok(Locales { langs })
+    }
+
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN).expect("embedded locales.toml")
+    }
+
+    // CAPTIONER_LOCALES points at a replacement for the embedded vocabularies.
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("CAPTIONER_LOCALES") {
+            Ok(path) if !path.trim().is_empty() => {
+                let src = std::fs::read_to_string(path.trim()).map_err(|e| format!("{path}: {e}"))?;
+                Self::parse(&src).map_err(|e| format!("{path}: {e}"))
+            }
+            _ => Ok(Self::builtin()),
+        }
+    }
+
+    // "fr", "fr-CA" and "FR_fr" all mean French; None for English and unknown languages.
+    pub fn get(&self, locale: &str) -> Option<(&str, &Lang)> {
+        let code = locale.trim().split(['-', '_']).next().unwrap_or("").to_lowercase();
+        self.langs.get_key_value(code.as_str()).map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn is_english(locale: &str) -> bool {
+        locale.trim().split(['-', '_']).next().is_some_and(|c| c.eq_ignore_ascii_case("en"))
+    }
+
+    // The language a title is most likely written in, if it isn't English: function words and
+    // vocabulary that only that language uses. Ties go to English.
+    pub fn detect(&self, title: &str) -> Option<&str> {
+        let words: Vec<String> = title.to_lowercase().split_whitespace().map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_string()).collect();
+        let toks = Tokens::new(title);
+        let english = words.iter().filter(|w| EN_STOPWORDS.contains(&w.as_str())).count();
+        let mut best: Option<(&str, usize)> = None;
+        let mut tie = false;
+        for (code, lang) in &self.langs {
+            let stop = words.iter().filter(|w| lang.stopwords.contains(w)).count();
+            let nouns = lang.nouns.iter().filter(|(en, n)| !n.word.eq_ignore_ascii_case(en) && toks.contains(&n.word)).count();
+            let score = stop + nouns;
+            match best {
+                Some((_, s)) if score == s => tie = true,
+                Some((_, s)) if score < s => {}
+                _ => {
+                    best = Some((code, score));
+                    tie = false;
+                }
+            }
+        }
+        best.filter(|&(_, s)| !tie && s > english).map(|(code, _)| code)
+    }
+
+    // Caption boilerplate in any configured language ("une photo de ...").
+    pub fn strip_prefix<'a>(&self, text: &'a str) -> &'a str {
+        let lower = text.to_lowercase();
+        self.langs
+            .values()
+            .flat_map(|l| &l.prefixes)
+            .find(|p| lower.starts_with(&p.to_lowercase()))
+            .and_then(|p| text.get(p.len()..))
+            .unwrap_or(text)
+    }
+}
+
+impl Lang {
+    pub fn template(&self, title: &str) -> String {
+        self.template.replace("{title}", title)
+    }
+
+    // The refined product in this language, or None if the category has no word here.
+    pub fn compose(&self, category: &str, values: &[Value], brand: Option<&str>) -> Option<String> {
+        let noun = self.nouns.get(category)?;
+        let mut adjectives: Vec<String> = Vec::new();
+        let mut phrases: Vec<String> = Vec::new();
+        let mut details: Vec<String> = Vec::new();
+        for v in values {
+            match v {
+                Value::Measure(m) => phrases.push(self.measure.replace("{}", m)),
+                Value::Word(w) => match self.words.get(&w.to_lowercase()) {
+                    Some(word @ Word { adj: Some(adj), .. }) => adjectives.push(self.agree(adj, word, noun)),
+                    Some(Word { phrase: Some(p), .. }) => phrases.push(p.clone()),
+                    Some(Word { noun: Some(d), .. }) => details.push(d.clone()),
+                    _ => {}
+                },
+            }
+        }
+        adjectives.dedup();
+
+        let mut words: Vec<String> = Vec::new();
+        match self.order {
+            Order::Before => {
+                words.extend(adjectives);
+                words.extend(brand.map(str::to_string));
+                words.push(noun.word.clone());
+            }
+            Order::After => {
+                words.push(noun.word.clone());
+                words.extend(brand.map(str::to_string));
+                words.extend(adjectives);
+            }
+        }
+        words.extend(phrases);
+        if !details.is_empty() {
+            words.push(self.with.clone());
+            words.push(join(&details, &self.and));
+        }
+        Some(crate::compose::sentence_case(&words.join(" ")))
+    }
+
+    fn agree(&self, adj: &str, w: &Word, noun: &Noun) -> String {
+        if w.invariable {
+            return adj.to_string();
+        }
+        if self.inflection == Inflection::German {
+            // No article in alt text, so the strong declension.
+            let ending = match (noun.plural, noun.gender) {
+                (true, _) => "e",
+                (false, Gender::Masculine) => "er",
+                (false, Gender::Feminine) => "e",
+                (false, Gender::Neuter) => "es",
+            };
+            return format!("{adj}{ending}");
+        }
+        let f = w.f.clone().unwrap_or_else(|| self.feminine(adj));
+        match (noun.plural, noun.gender == Gender::Feminine) {
+            (false, false) => adj.to_string(),
+            (false, true) => f,
+            (true, false) => w.pl.clone().unwrap_or_else(|| self.plural(adj)),
+            (true, true) => w.fpl.clone().unwrap_or_else(|| self.plural(&f)),
+        }
+    }
+
+    fn feminine(&self, adj: &str) -> String {
+        match self.inflection {
+            Inflection::French if !adj.ends_with('e') => format!("{adj}e"),
+            Inflection::Spanish if adj.ends_with('o') => format!("{}a", &adj[..adj.len() - 1]),
+            _ => adj.to_string(),
+        }
+    }
+
+    fn plural(&self, adj: &str) -> String {
+        let last = adj.chars().last().unwrap_or(' ');
+        match self.inflection {
+            Inflection::French if !matches!(last, 's' | 'x') => format!("{adj}s"),
+            Inflection::Spanish if "aeiouáéíóú".contains(last) => format!("{adj}s"),
+            Inflection::Spanish => format!("{adj}es"),
+            _ => adj.to_string(),
+        }
+    }
+}
+
+fn join(items: &[String], and: &str) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.clone(),
+        [init @ .., last] => format!("{} {and} {last}", init.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composes_with_placement_and_agreement() {
+        let locales = Locales::builtin();
+        let say = |code: &str, category: &str, values: &[Value]| locales.get(code).unwrap().1.compose(category, values, None).unwrap();
+        let shoe = [Value::Word("black"), Value::Word("leather"), Value::Word("buckle")];
+        assert_eq!(say("fr", "shoe", &shoe), "Chaussure noire en cuir avec boucle");
+        assert_eq!(say("de", "shoe", &shoe), "Schwarzer Schuh aus Leder mit Schnalle");
+        assert_eq!(say("es", "shoe", &shoe), "Zapato negro de cuero con hebilla");
+        assert_eq!(say("de", "dress", &[Value::Word("red"), Value::Word("silk")]), "Rotes Kleid aus Seide");
+        assert_eq!(say("es", "sunglasses", &[Value::Word("black")]), "Gafas de sol negras");
+        assert_eq!(say("fr", "bag", &[Value::Word("navy"), Value::Word("zipper"), Value::Word("logo")]), "Sac bleu marine avec fermeture éclair et logo");
+        assert_eq!(say("fr", "bottle", &[Value::Word("white"), Value::Measure("750 ml"), Value::Word("unknown")]), "Bouteille blanche de 750 ml");
+        assert!(locales.get("fr-CA").is_some() && locales.get("en").is_none());
+        assert!(locales.get("fr").unwrap().1.compose("gizmo", &[], None).is_none());
+    }
+
+    #[test]
+    fn detects_title_language_and_strips_prefixes() {
+        let locales = Locales::builtin();
+        assert_eq!(locales.detect("Robe longue en soie noire"), Some("fr"));
+        assert_eq!(locales.detect("Zapato de cuero con hebilla"), Some("es"));
+        assert_eq!(locales.detect("Schwarzer Rucksack mit Reißverschluss"), Some("de"));
+        assert_eq!(locales.detect("Black leather tote with zipper"), None);
+        assert_eq!(locales.detect("Oxford"), None);
+        assert_eq!(locales.strip_prefix("Une photo de chaussure noire"), "chaussure noire");
+    }
+}
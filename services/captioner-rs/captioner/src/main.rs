@@ -1,8 +1,33 @@
+mod alt_policy;
+mod candidates;
+mod cdn;
+mod clip_text;
+mod compat;
+mod compose;
+mod describe;
 mod engine;
+mod existing;
+mod fetch;
+mod lint;
+mod locale;
+mod matching;
+mod people;
+mod pipeline;
+mod rules;
+mod seo;
+mod speech;
+mod sigv4;
+mod sources;
+mod taxonomy;
+mod trace;
+mod upload;
+mod url_policy;
 
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{DefaultBodyLimit, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
     routing::{get, post},
 };
 use serde::{Deserialize, Serialize};
@@ -13,8 +38,6 @@ use std::sync::{
 };
 use std::collections::HashMap;
 use std::time::Instant as StdInstant;
-#[cfg(feature = "turbo-ffi")]
-use std::time::Instant;
 
 use tokio::{signal, time::Duration};
 #[cfg(feature = "turbo-ffi")]
@@ -30,11 +53,8 @@ use std::path::PathBuf;
 use tracing_subscriber::EnvFilter;
 
 use captioner::{ApiError, ErrBody};
-#[cfg(not(feature = "turbo-ffi"))]
-use captioner::decode_image;
-#[cfg(feature = "turbo-ffi")]
-use captioner::decode;
 
+use base64::Engine as _;
 use bytes::Bytes;
 use reqwest::Client;
 use unicode_segmentation::UnicodeSegmentation;
@@ -43,6 +63,17 @@ struct AppState {
     model_name: &'static str,
     request_count: AtomicU64,
     http: Client,
+    // Policy-guarded client for user-supplied image URLs
+    fetcher: fetch::Fetcher,
+    // Product categories and attribute vocabularies for alt text refinement
+    taxonomy: taxonomy::Taxonomy,
+    locales: locale::Locales,
+    // Alt text length policy, default and per shop
+    alt_policies: alt_policy::PolicyBook,
+    // Per-shop rewrite rules, reloaded when their files change
+    rules: rules::RuleBook,
+    // Stages every caption request runs through
+    pipeline: pipeline::CaptionPipeline,
     // Optional remote inference endpoints for GPU-backed model; tried in order
     remote_infer_urls: Vec<String>,
     // Round-robin index for remote endpoints
@@ -51,21 +82,132 @@ struct AppState {
     remote_backoff: Mutex<HashMap<String, StdInstant>>,
     // Backoff duration in seconds
     remote_backoff_secs: u64,
+    // Unlocks `"debug": true` (sent as x-admin-key); debug is refused when unset
+    admin_key: Option<String>,
     #[cfg(feature = "turbo-ffi")]
     decode_limit: Arc<Semaphore>,
-    engine_tx: tokio::sync::mpsc::Sender<engine::Job>,
+    // None when the model file is missing: remote endpoints and model-free fallbacks only
+    engine_tx: Option<tokio::sync::mpsc::Sender<engine::Job>>,
+    // CLIP text encoder for reranking; None when its model files are missing
+    text_encoder: Option<Arc<clip_text::TextEncoder>>,
 }
 
-#[derive(Deserialize)]
+impl AppState {
+    fn mode(&self) -> &'static str {
+        match (self.engine_tx.is_some(), self.remote_infer_urls.is_empty()) {
+            (true, true) => "local",
+            (true, false) => "remote+local",
+            (false, false) => "remote-only (no local model)",
+            (false, true) => "template-only (no local model)",
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
 struct CaptionReq {
+    #[serde(default)]
     image_url: String,
     product_title: Option<String>,
+    // Uploaded image (multipart, raw body or `data:` URI); never read from JSON directly.
+    #[serde(skip)]
+    image_bytes: Option<Bytes>,
+    // Tenant (shop domain) whose alt text policy applies
+    shop: Option<String>,
+    // Per-request length policy, on top of the shop's
+    alt_policy: Option<alt_policy::PolicyOverride>,
+    // Return the refinement trace (admin key required)
+    #[serde(default)]
+    debug: bool,
+    // Product context from the shop; trusted over the model when refining
+    vendor: Option<String>,
+    product_type: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    variant: VariantOptions,
+    // Keep the vendor in the alt text ("Allbirds white sneaker"); stripped by default
+    #[serde(default)]
+    include_brand: bool,
+    // Return up to this many alternative alt texts
+    candidates: Option<usize>,
+    // Also return a few sentences for complex images (charts, bundles)
+    #[serde(default)]
+    long_description: bool,
+    // Alt text the merchant already has; kept, improved or replaced
+    existing_alt: Option<String>,
+    // Output language ("fr", "de-CH"); detected from the title when unset
+    locale: Option<String>,
+    // Several languages at once (Shopify Markets): the first is `alt_text`, the rest `translations`
+    #[serde(default)]
+    locales: Vec<String>,
+    // Resource the translations register on (gid://shopify/MediaImage/…)
+    resource_id: Option<String>,
+    // Digest of the alt text as Shopify stores it; computed from `alt_text` when unset
+    translatable_content_digest: Option<String>,
+}
+
+// Selected variant's options, as the shop names them.
+#[derive(Deserialize, Default, Clone)]
+struct VariantOptions {
+    color: Option<String>,
+    size: Option<String>,
+    material: Option<String>,
+}
+
+impl VariantOptions {
+    fn is_empty(&self) -> bool {
+        self.color.is_none() && self.size.is_none() && self.material.is_none()
+    }
+
+    // The option that fills a taxonomy attribute of the same name.
+    fn option(&self, attribute: &str) -> Option<&str> {
+        match attribute {
+            "color" => self.color.as_deref(),
+            "material" => self.material.as_deref(),
+            _ => None,
+        }
+        .filter(|o| !o.trim().is_empty())
+    }
+}
+
+impl CaptionReq {
+    // Title with the vendor's name removed ("ACME Widget" -> "Widget").
+    fn title_without_vendor(&self) -> Option<String> {
+        let title = self.product_title.as_deref()?;
+        let title = match self.vendor.as_deref() {
+            Some(v) if !v.trim().is_empty() => matching::strip_phrase(title, v),
+            _ => title.trim().to_string(),
+        };
+        Some(title).filter(|t| !t.is_empty())
+    }
 }
 
 #[derive(Serialize)]
 struct CaptionResp {
     alt_text: String,
     tags: Vec<String>,
+    // Answered by a fallback below the best configured level (see `source`)
+    degraded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<pipeline::CaptionSource>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    candidates: Vec<candidates::Candidate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    long_description: Option<String>,
+    // Decision on the request's `existing_alt`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    existing_alt: Option<existing::ExistingAlt>,
+    // Language of `alt_text` when a locale was requested or detected ("en" if it fell back)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locale: Option<String>,
+    // translationsRegister variables for `locales` after the first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    translations: Option<locale::TranslationsRegister>,
+    // Requested locales with no wording; nothing to register for them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    untranslated_locales: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    debug: Option<trace::DebugTrace>,
 }
 
 #[derive(Deserialize)]
@@ -81,49 +223,58 @@ struct BulkResp {
 #[derive(Serialize)]
 #[serde(tag = "status", content = "data")]
 enum ItemOutcome {
-    Ok(CaptionResp),
-    #[allow(dead_code)]
+    Ok(Box<CaptionResp>),
     Error(ErrBody),
 }
 
 type Result<T> = std::result::Result<T, ApiError>;
 
-fn make_caption(req: &CaptionReq) -> Result<CaptionResp> {
+fn make_caption(req: &CaptionReq, policy: &alt_policy::AltTextPolicy) -> Result<CaptionResp> {
+    validate_image(req)?;
+
+    let title = if req.include_brand { req.product_title.clone() } else { req.title_without_vendor() };
+    let base = title.as_deref().unwrap_or("Product photo").trim();
+
+    let alt = policy.apply(&format!("{base} on a plain background"));
+
+    Ok(CaptionResp { alt_text: alt, tags: vec![], degraded: false, source: None, candidates: vec![], long_description: None, existing_alt: None, locale: None, translations: None, untranslated_locales: Vec::new(), debug: None })
+}
+
+// Either uploaded bytes or a supported image URL must be present.
+fn validate_image(req: &CaptionReq) -> Result<()> {
+    if req.image_bytes.is_some() {
+        return Ok(());
+    }
     if req.image_url.trim().is_empty() {
         return Err(ApiError::BadRequest(Cow::Borrowed("image_url required")));
     }
-    if !(req.image_url.starts_with("http://") || req.image_url.starts_with("https://")) {
+    if !(is_http_url(&req.image_url) || req.image_url.starts_with("file://") || req.image_url.starts_with("s3://")) {
         return Err(ApiError::BadRequest(Cow::Borrowed(
-            "image_url must be http(s)",
+            "image_url must be http(s), file:// or s3://",
         )));
     }
+    Ok(())
+}
 
-    let base = req
-        .product_title
-        .as_deref()
-        .unwrap_or("Product photo")
-        .trim();
-
-    let mut alt = format!("{base} on a plain background");
-    if alt.len() > 125 {
-        alt.truncate(125);
-    }
-
-    Ok(CaptionResp { alt_text: alt, tags: vec![] })
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
 }
 
+// Boilerplate captioning models put in front of the description.
+const CAPTION_PREFIXES: &[&str] = &[
+    "a product photo of ",
+    "a studio product photo of ",
+    "a studio product shot of ",
+    "a product image of ",
+    "a photo of ",
+    "an image of ",
+    "a picture of ",
+];
+
 fn clean_caption(mut s: String) -> String {
     let orig = s.clone();
     let lower = s.to_lowercase();
-    for p in [
-        "a product photo of ",
-        "a studio product photo of ",
-        "a studio product shot of ",
-        "a product image of ",
-        "a photo of ",
-        "an image of ",
-        "a picture of ",
-    ] {
+    for p in CAPTION_PREFIXES {
         if lower.starts_with(p) {
             s = s.split_at(p.len()).1.to_string();
             break;
@@ -142,297 +293,239 @@ fn clean_caption(mut s: String) -> String {
     s
 }
 
+// Matching is on word/phrase boundaries (see matching.rs), never raw substrings.
 fn contains_any(haystack: &str, needles: &[&str]) -> bool {
-    let lower = haystack.to_lowercase();
-    needles.iter().any(|w| lower.contains(w))
+    let toks = matching::Tokens::new(haystack);
+    needles.iter().any(|w| toks.contains(w))
 }
 
-fn pick_first<'a>(candidates: &'a[&str], tags: &[String]) -> Option<&'a str> {
-    for &c in candidates {
-        if tags.iter().any(|t| t.eq_ignore_ascii_case(c)) { return Some(c); }
-    }
-    None
+fn pick_first<'a>(candidates: &[&'a str], tags: &[String]) -> Option<&'a str> {
+    candidates.iter().copied().find(|c| tags.iter().any(|t| matching::same_phrase(t, c)))
 }
 
-fn pick_from_text<'a>(candidates: &'a[&str], text: &str) -> Option<&'a str> {
-    let lower = text.to_lowercase();
+// Prefer the longest matching phrase ("t-shirt" over "shirt"), then list order.
+fn pick_from_text<'a>(candidates: &[&'a str], text: &str) -> Option<&'a str> {
+    let toks = matching::Tokens::new(text);
+    let mut best: Option<(&'a str, usize)> = None;
     for &c in candidates {
-        if lower.contains(c) { return Some(c); }
+        let len = toks.find_all(c).iter().map(|(s, e)| e - s).max();
+        if let Some(len) = len && best.is_none_or(|(_, l)| len > l) {
+            best = Some((c, len));
+        }
     }
-    None
+    best.map(|(c, _)| c)
 }
 
+// Heuristics to detect low-value/person-centric captions
+const PEOPLE: &[&str] = &["woman","women","man","men","person","people","girl","boy","lady","gentleman","model","wearing","holding","sitting","standing","smiling","posing"];
+
 // Compose a product-focused alt text from tags/title when the model caption is generic or person-centric.
-fn refine_alt(product_title: Option<&str>, current_alt: &str, tags: &[String]) -> String {
-    // Heuristics to detect low-value/person-centric captions
-    const PEOPLE: &[&str] = &["woman","women","man","men","person","people","girl","boy","lady","gentleman","model","wearing","holding","sitting","standing","smiling","posing"];
-    const PRODUCT_NOUNS: &[&str] = &["shoe","sneaker","boot","loafer","heel","sandal","watch","bag","backpack","wallet","tote","duffle","crossbody","shirt","t-shirt","dress","jacket","pants","jeans","skirt","sweater","hoodie","sweatshirt","coat","blazer","top","hat","sunglasses","glasses","ring","necklace","earrings","phone","case","laptop","tablet","headphones","earbuds","mug","bottle","cup","belt","scarf"];
-    const COLORS: &[&str] = &["black","white","gray","charcoal","red","blue","navy","teal","green","olive","yellow","orange","brown","beige","tan","cream","ivory","khaki","purple","maroon","burgundy","pink"];
-    const MATERIALS: &[&str] = &["leather","suede","cotton","wool","denim","silk","canvas","mesh","rubber","plastic","nylon","polyester","stainless","steel","gold","silver","ceramic"];
-    const DETAILS: &[&str] = &["zipper","buckle","strap","logo","matte","glossy","insulated"];
-    const SLEEVES: &[&str] = &["sleeveless","short sleeve","long sleeve","3/4 sleeve","cap sleeve"];
-    const NECKLINES: &[&str] = &["cowl neck","v-neck","crew neck","turtleneck","halter","off shoulder","one shoulder","boat neck","square neck","sweetheart"];
-    const EMBELLISH: &[&str] = &["sequin","sequined","lace","ribbed","velvet","satin","ruched","pleated","wrap","peplum","crop"];
-
-    // If a title is supplied, always refine to align with it.
+// Categories and attribute vocabularies come from the taxonomy (taxonomy.toml). What the shop
+// sent (variant options, product tags, product type, title) is trusted over the model's tags.
+// The trace records which branch was taken and where each value came from.
+fn refine_alt_traced(tax: &taxonomy::Taxonomy, req: &CaptionReq, current_alt: &str, model_tags: &[String]) -> (String, trace::RefineTrace) {
+    refine_alt_omitting(tax, req, current_alt, model_tags, &[])
+}
+
+// refine_alt_traced, leaving out the named attribute types (for alternative candidates).
+fn refine_alt_omitting(
+    tax: &taxonomy::Taxonomy,
+    req: &CaptionReq,
+    current_alt: &str,
+    model_tags: &[String],
+    omit: &[&str],
+) -> (String, trace::RefineTrace) {
+    use trace::Source;
+    let mut tr = trace::RefineTrace::default();
+    // Brand words never count as attributes ("Black Diamond" is not a color).
+    let title = req.title_without_vendor();
+    let product_title = title.as_deref();
+    let product_type = req.product_type.as_deref().filter(|t| !t.trim().is_empty());
+    let vendor = req.vendor.as_deref().filter(|v| req.include_brand && !v.trim().is_empty());
+    let unbranded = |s: String| match req.vendor.as_deref() {
+        Some(v) if !req.include_brand && !v.trim().is_empty() => matching::strip_phrase(&s, v),
+        _ => s,
+    };
+
+    let color = tax.attribute("color");
+    // If the shop told us anything about the product, always refine to align with it.
     let current_ok = product_title.is_none()
+        && product_type.is_none()
+        && req.tags.is_empty()
+        && req.variant.is_empty()
         && !contains_any(current_alt, PEOPLE)
-        && pick_from_text(PRODUCT_NOUNS, current_alt).is_some()
-        && pick_from_text(COLORS, current_alt).is_some();
+        && tax.category_in_text(current_alt).is_some()
+        && color.is_some_and(|c| pick_from_text(&c.values(), current_alt).is_some());
     if current_ok {
-        return current_alt.to_string();
+        tr.branch = trace::Branch::CaptionKept;
+        tr.category = tax.category_in_text(current_alt).map(|c| c.name.clone());
+        tr.category_source = Some(Source::Caption);
+        return (unbranded(current_alt.to_string()), tr);
     }
 
-    // Synonyms for some product categories, to anchor attribute lookup near the intended item
-    fn cat_synonyms(cat: &str) -> Vec<&str> {
-        match cat {
-            "pants" => vec!["pants","trousers","slacks","chinos","denim","jeans","leggings"],
-            "sweater" => vec!["sweater","jumper","pullover","knit"],
-            "shirt" => vec!["shirt","top","blouse"],
-            "t-shirt" => vec!["t-shirt","tee","tee-shirt"],
-            _ => vec![cat],
-        }
+    // Find a candidate term (color/material/detail) that appears adjacent to any category phrase
+    fn find_term_near<'a>(text: &str, cats: &[&str], terms: &[&'a str]) -> Option<&'a str> {
+        let toks = matching::Tokens::new(text);
+        let cat_spans: Vec<(usize, usize)> = cats.iter().flat_map(|c| toks.find_all(c)).collect();
+        let term_where = |near: &dyn Fn((usize, usize), (usize, usize)) -> bool| {
+            terms.iter().copied().find(|t| {
+                toks.find_all(t).into_iter().any(|ts| cat_spans.iter().any(|&cs| near(ts, cs)))
+            })
+        };
+        // Directly before the category, directly after it, then after a connector ("bag in black")
+        term_where(&|(_, te), (cs, _)| te == cs)
+            .or_else(|| term_where(&|(ts, _), (_, ce)| ts == ce))
+            .or_else(|| term_where(&|(ts, _), (_, ce)| {
+                ts == ce + 1 && toks.get(ce).is_some_and(|w| ["in", "with", "of", "on"].contains(&w))
+            }))
     }
 
-    // Find a candidate term (color/material/detail) that appears adjacent to any category token
-    fn find_term_near<'a>(text: &str, cats: &[&str], terms: &'a[&str]) -> Option<&'a str> {
-        let lower = text.to_lowercase();
-        let words: Vec<&str> = lower.unicode_words().collect();
-        let is_term = |tok: &str| -> Option<&'a str> { for &c in terms { if c == tok { return Some(c); } } None };
-        for i in 0..words.len() {
-            if cats.contains(&words[i]) {
-                if i > 0 { if let Some(c) = is_term(words[i-1]) { return Some(c); } }
-                if i + 1 < words.len() { if let Some(c) = is_term(words[i+1]) { return Some(c); } }
-                if i + 2 < words.len() && ["in","with","of","on"].contains(&words[i+1]) {
-                    if let Some(c) = is_term(words[i+2]) { return Some(c); }
-                }
-            }
-        }
-        for i in 0..words.len().saturating_sub(1) {
-            if cats.contains(&words[i+1]) { if let Some(c) = is_term(words[i]) { return Some(c); } }
-        }
-        None
-    }
-
-    // Determine requested category if title mentions it
-    let wanted_category = product_title.and_then(|t| pick_from_text(PRODUCT_NOUNS, t));
-    // Determine category first, prefer title, then tags, then caption
-    let mut category = wanted_category.or_else(|| pick_first(PRODUCT_NOUNS, tags));
-    if category.is_none() {
-        if let Some(title) = product_title { category = pick_from_text(PRODUCT_NOUNS, title); }
-    }
-    if category.is_none() { category = pick_from_text(PRODUCT_NOUNS, current_alt); }
-
-    // Build from tags and, if needed, title
-    let mut color = None;
-    if let Some(cat) = wanted_category.or(category) {
-        let tokens = cat_synonyms(cat);
-        color = find_term_near(current_alt, tokens.as_slice(), COLORS);
-    }
-    if color.is_none() { color = pick_first(COLORS, tags); }
-    if color.is_none() { color = product_title.and_then(|t| pick_from_text(COLORS, t)); }
-    // Intentionally do NOT fall back to any color in the caption when it isn't
-    // near the requested category; this avoids picking the sweater's color for pants.
-
-    // Only include a material if it is near the chosen category (to avoid borrowing sweater materials for pants)
-    let mut material = None;
-    if let Some(cat) = wanted_category.or(category) {
-        let tokens = cat_synonyms(cat);
-        material = find_term_near(current_alt, tokens.as_slice(), MATERIALS);
-    }
-    if material.is_none() { material = pick_first(MATERIALS, tags); }
-    if material.is_none() { material = product_title.and_then(|t| pick_from_text(MATERIALS, t)); }
-
-    // Optional style attributes (sleeves, neckline, embellishment)
-    let mut sleeve = None;
-    if let Some(cat) = wanted_category.or(category) { let tokens = cat_synonyms(cat); sleeve = find_term_near(current_alt, tokens.as_slice(), SLEEVES); }
-    if sleeve.is_none() { sleeve = pick_first(SLEEVES, tags); }
-    if sleeve.is_none() { sleeve = product_title.and_then(|t| pick_from_text(SLEEVES, t)); }
-
-    let mut neckline = None;
-    if let Some(cat) = wanted_category.or(category) { let tokens = cat_synonyms(cat); neckline = find_term_near(current_alt, tokens.as_slice(), NECKLINES); }
-    if neckline.is_none() { neckline = pick_first(NECKLINES, tags); }
-    if neckline.is_none() { neckline = product_title.and_then(|t| pick_from_text(NECKLINES, t)); }
-
-    let mut emb = None;
-    if let Some(cat) = wanted_category.or(category) { let tokens = cat_synonyms(cat); emb = find_term_near(current_alt, tokens.as_slice(), EMBELLISH); }
-    if emb.is_none() { emb = pick_first(EMBELLISH, tags); }
-    if emb.is_none() { emb = product_title.and_then(|t| pick_from_text(EMBELLISH, t)); }
+    // Determine category first: title, product type, product tags, then model tags and caption
+    let category = product_title.and_then(|t| tax.category_in_text(t)).map(|c| (c, Source::Title))
+        .or_else(|| product_type.and_then(|t| tax.category_in_text(t)).map(|c| (c, Source::ProductType)))
+        .or_else(|| tax.category_in_tags(&req.tags).map(|c| (c, Source::ProductTag)))
+        .or_else(|| tax.category_in_tags(model_tags).map(|c| (c, Source::Tag)))
+        .or_else(|| tax.category_in_text(current_alt).map(|c| (c, Source::Caption)));
 
     // If we still don't know the category, fall back to current_alt with people words stripped
-    if category.is_none() {
-        let mut s = current_alt.to_string();
-        for w in PEOPLE { s = s.replace(w, ""); }
-        return clean_caption(s);
-    }
-
-    let category = category.unwrap();
-    let mut parts: Vec<&str> = Vec::new();
-    if let Some(sl) = sleeve { parts.push(sl); }
-    if let Some(e) = emb { parts.push(e); }
-    if let Some(nk) = neckline { parts.push(nk); }
-    if let Some(m) = material { parts.push(m); }
-    parts.push(category);
-    if let Some(c) = color { parts.push(c); }
-
-    // Optional detail
-    if let Some(d) = pick_first(DETAILS, tags)
-        .or_else(|| product_title.and_then(|t| pick_from_text(DETAILS, t)))
-        .or_else(|| pick_from_text(DETAILS, current_alt)) { parts.push(d); }
-
-    let mut out = parts.join(" ");
-    out = out.trim().to_string();
-    // Guard against overly short outputs: prefer a short, correct phrase over a long, incorrect one.
-    if out.split_whitespace().count() < 2 {
-        // Try to add a descriptor from title or tags only (do not borrow from unrelated caption tokens)
-        let desc = product_title.and_then(|t| pick_from_text(COLORS, t))
-            .or_else(|| product_title.and_then(|t| pick_from_text(MATERIALS, t)))
-            .or_else(|| pick_first(COLORS, tags))
-            .or_else(|| pick_first(MATERIALS, tags));
-        if let Some(d) = desc {
-            out = format!("{} {}", d, category);
+    let Some((category, category_source)) = category else {
+        tr.branch = trace::Branch::NoCategory;
+        let s = matching::remove_phrases(current_alt, PEOPLE);
+        return (unbranded(clean_caption(s)), tr);
+    };
+    tr.category = Some(category.name.clone());
+    tr.category_source = Some(category_source);
+    let anchors: Vec<&str> = category.names().collect();
+
+    let mut phrase = compose::Phrase::new(&category.name);
+    for (name, attr) in tax.attributes_of(category).filter(|(n, _)| !omit.contains(n)) {
+        let option = req.variant.option(name);
+        let value = if attr.is_measure() {
+            req.variant.size.as_deref().and_then(|s| attr.measure_in(s)).map(|v| (v, Source::Variant))
+                .or_else(|| req.tags.iter().find_map(|t| attr.measure_in(t)).map(|v| (v, Source::ProductTag)))
+                .or_else(|| model_tags.iter().find_map(|t| attr.measure_in(t)).map(|v| (v, Source::Tag)))
+                .or_else(|| product_title.and_then(|t| attr.measure_in(t)).map(|v| (v, Source::Title)))
+                .or_else(|| attr.measure_in(current_alt).map(|v| (v, Source::Caption)))
         } else {
-            // Leave as just the category if no safe descriptor is available
-            out = category.to_string();
+            let values = attr.values();
+            // A variant option outside the vocabulary ("Midnight") is still the shop's word for it.
+            let trusted = option.map(|o| (pick_from_text(&values, o).unwrap_or(o), Source::Variant))
+                .or_else(|| pick_first(&values, &req.tags).map(|v| (v, Source::ProductTag)))
+                .or_else(|| product_type.and_then(|t| pick_from_text(&values, t)).map(|v| (v, Source::ProductType)));
+            let found = trusted.or_else(|| if attr.is_detail() {
+                // Features ("with buckle") are safe to take from anywhere in the caption
+                pick_first(&values, model_tags).map(|v| (v, Source::Tag))
+                    .or_else(|| product_title.and_then(|t| pick_from_text(&values, t)).map(|v| (v, Source::Title)))
+                    .or_else(|| pick_from_text(&values, current_alt).map(|v| (v, Source::Caption)))
+            } else {
+                // Intentionally do NOT fall back to any value in the caption when it isn't
+                // near the requested category; this avoids picking the sweater's color for pants.
+                find_term_near(current_alt, &anchors, &values).map(|v| (v, Source::CaptionNearCategory))
+                    .or_else(|| pick_first(&values, model_tags).map(|v| (v, Source::Tag)))
+                    .or_else(|| product_title.and_then(|t| pick_from_text(&values, t)).map(|v| (v, Source::Title)))
+            });
+            found.map(|(v, s)| (v.trim().to_lowercase(), s))
+        };
+        if let Some((v, source)) = value {
+            tr.choose(name, &v, source);
+            phrase = attr.place(phrase, &v);
         }
     }
-    out
-}
 
-async fn fetch_bytes(http: &Client, url: &str) -> Result<Bytes> {
-    let resp = http.get(url).send().await?;
-    if !resp.status().is_success() {
-        return Err(ApiError::BadRequest(Cow::Borrowed(
-            "image url not fetchable",
-        )));
+    // Guard against overly short outputs: prefer a short, correct phrase over a long, incorrect one.
+    if phrase.compose().split_whitespace().count() < 2 {
+        // Try to add a descriptor from title or tags only (do not borrow from unrelated caption tokens)
+        let descriptors: Vec<(&str, &taxonomy::Attribute)> = ["color", "material"].iter().filter(|n| !omit.contains(n)).filter_map(|n| tax.attribute(n).map(|a| (*n, a))).collect();
+        let desc = product_title
+            .and_then(|t| descriptors.iter().find_map(|&(n, a)| pick_from_text(&a.values(), t).map(|v| (n, a, v, Source::Title))))
+            .or_else(|| descriptors.iter().find_map(|&(n, a)| pick_first(&a.values(), &req.tags).map(|v| (n, a, v, Source::ProductTag))))
+            .or_else(|| descriptors.iter().find_map(|&(n, a)| pick_first(&a.values(), model_tags).map(|v| (n, a, v, Source::Tag))));
+        // Leave as just the category if no safe descriptor is available
+        if let Some((name, attr, d, source)) = desc {
+            tr.branch = trace::Branch::ShortOutputGuard;
+            tr.choose(name, d, source);
+            phrase = attr.place(phrase, d);
+        }
     }
-    resp.bytes()
-        .await
-        .map_err(|_| ApiError::BadRequest(Cow::Borrowed("read body failed")))
+    if let Some(v) = vendor {
+        phrase = phrase.brand(v);
+    }
+    (phrase.compose(), tr)
 }
 
 async fn health(State(state): State<Arc<AppState>>) -> String {
     let n = state.request_count.load(Ordering::Relaxed);
-    format!("ok\nmodel={}; requests={}\n", state.model_name, n)
+    let model = if state.engine_tx.is_some() { state.model_name } else { "none" };
+    format!("ok\nmodel={}; requests={}; mode={}\n", model, n, state.mode())
 }
 
 async fn caption(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<CaptionReq>,
-) -> Result<Json<CaptionResp>> {
+    headers: HeaderMap,
+    upload::CaptionInput(req): upload::CaptionInput,
+) -> Response {
     info!("caption called");
 
-    state.request_count.fetch_add(1, Ordering::Relaxed);
-
-    if req.image_url.trim().is_empty() {
-        return Err(ApiError::BadRequest(Cow::Borrowed("image_url required")));
+    if req.debug && let Err(e) = trace::authorize(state.admin_key.as_deref(), &headers) {
+        return e.into_response();
     }
+    state.request_count.fetch_add(1, Ordering::Relaxed);
 
-    if !(req.image_url.starts_with("http://") || req.image_url.starts_with("https://")) {
-        return Err(ApiError::BadRequest(Cow::Borrowed(
-            "image_url must be http(s)",
-        )));
-    }
-
-    // Prefer remote inference when configured; fallback to local engine.
-    let eng_out = if !state.remote_infer_urls.is_empty() {
-        let n = state.remote_infer_urls.len();
-        let start = state.remote_rr.fetch_add(1, Ordering::Relaxed) % n.max(1);
-        let mut try_urls = rotate_urls(&state.remote_infer_urls, start);
-        try_urls = filter_backoff(&state, try_urls);
-        match remote_infer_failover_backoff(&state, &try_urls, &req.image_url, req.product_title.as_deref()).await {
-            Ok(o) => o,
-            Err(e) => {
-                tracing::warn!(err = %e, "all remote inference endpoints failed; falling back to local");
-                local_engine_run(&state, &req).await?
-            }
-        }
-    } else {
-        local_engine_run(&state, &req).await?
-    };
-
-    let base = make_caption(&req)?;
-    let raw = if !eng_out.caption.is_empty() { clean_caption(eng_out.caption.clone()) } else { base.alt_text };
-    let mut alt = refine_alt(req.product_title.as_deref(), &raw, &eng_out.tags);
-    // Truncate without cutting mid‑word
-    if alt.len() > 125 {
-        let mut cut = 125usize;
-        if let Some(pos) = alt[..125].rfind(' ') { cut = pos; }
-        alt.truncate(cut);
-        // Remove dangling connectors if truncation leaves them at the end
-        for conn in [" with", " and", " of", " on", " in", " at", " for", " to", " by"] {
-            if alt.ends_with(conn) { alt.truncate(alt.len() - conn.len()); break; }
-        }
+    match state.pipeline.run(&state, req).await {
+        Ok(ctx) => Json(ctx.into_resp()).into_response(),
+        Err(e) => e.into_response(),
     }
-    let resp = CaptionResp { alt_text: alt, tags: eng_out.tags };
-
-    Ok(Json(resp))
-}
-
-async fn local_engine_run(state: &Arc<AppState>, req: &CaptionReq) -> Result<engine::EngineOutput> {
-    let bytes = fetch_bytes(&state.http, &req.image_url).await?;
-    let img = {
-        let span = tracing::info_span!("caption", model = state.model_name);
-        let _enter = span.enter();
-        #[cfg(feature = "turbo-ffi")]
-        {
-            let _permit = state.decode_limit.clone().acquire_owned().await.unwrap();
-            let t0 = Instant::now();
-            let decoded = decode(&bytes).await?;
-            tracing::info!(decode_ms = t0.elapsed().as_millis(), "jpeg decoded");
-            decoded
-        }
-
-        #[cfg(not(feature = "turbo-ffi"))]
-        {
-            decode_image(bytes.clone()).await?
-        }
-    };
-
-    let (tx, rx) = tokio::sync::oneshot::channel();
-    state
-        .engine_tx
-        .send(engine::Job { image: img, title: req.product_title.clone(), tx })
-        .await
-        .map_err(|_| ApiError::Internal)?;
-    let eng_out = rx.await.map_err(|_| ApiError::Internal)??;
-    Ok(eng_out)
 }
 
+// The image goes as base64, read through the fetcher's URL policy; endpoints are never sent
+// a caller's URL to fetch.
 #[derive(Serialize, Deserialize)]
 struct RemoteInferReq<'a> {
-    image_url: &'a str,
+    image_b64: String,
     #[serde(skip_serializing_if = "Option::is_none")] title: Option<&'a str>,
+    // Ask for up to this many beam-search alternatives
+    #[serde(skip_serializing_if = "Option::is_none")] n_best: Option<usize>,
 }
-#[derive(Serialize, Deserialize)]
-struct RemoteInferResp { caption: String, #[serde(default)] tags: Vec<String> }
 
-async fn remote_infer(http: &Client, base_url: &str, image_url: &str, title: Option<&str>) -> Result<engine::EngineOutput> {
-    let url = format!("{}/v1/infer", base_url.trim_end_matches('/'));
-    let req = RemoteInferReq { image_url, title };
-    let resp = http
-        .post(url)
-        .timeout(Duration::from_secs(12))
-        .json(&req)
-        .send()
-        .await
-        .map_err(|_| ApiError::BadRequest(Cow::Borrowed("remote infer: send failed")))?;
-    if !resp.status().is_success() {
-        return Err(ApiError::BadRequest(Cow::Borrowed("remote infer: bad status")));
+impl<'a> RemoteInferReq<'a> {
+    fn new(req: &'a CaptionReq, image: &Bytes) -> Self {
+        RemoteInferReq {
+            image_b64: base64::engine::general_purpose::STANDARD.encode(image),
+            title: req.product_title.as_deref(),
+            n_best: req.candidates.filter(|&n| n > 1),
+        }
     }
-    let r: RemoteInferResp = resp.json().await.map_err(|_| ApiError::Internal)?;
-    Ok(engine::EngineOutput { embed_dim: 0, embedding: vec![], caption: r.caption, tags: r.tags })
+}
+#[derive(Serialize, Deserialize)]
+struct RemoteInferResp {
+    caption: String,
+    #[serde(default)] tags: Vec<String>,
+    #[serde(default)] tag_scores: Vec<f32>,
+    // n-best captions, best first, when `n_best` was requested
+    #[serde(default)] captions: Vec<RemoteCaption>,
+    // Text read from the image, for endpoints that run OCR
+    #[serde(default)] text: Vec<String>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+struct RemoteCaption { caption: String, score: Option<f32> }
+
 enum RemoteError { Status(u16), Send, Parse }
 
-async fn remote_infer_try(http: &Client, base_url: &str, image_url: &str, title: Option<&str>) -> std::result::Result<engine::EngineOutput, RemoteError> {
+// Output plus what only remote endpoints report.
+struct RemoteOutput {
+    output: engine::EngineOutput,
+    tag_scores: Vec<f32>,
+    alternatives: Vec<RemoteCaption>,
+    text: Vec<String>,
+}
+
+async fn remote_infer_try(http: &Client, base_url: &str, req: &RemoteInferReq<'_>) -> std::result::Result<RemoteOutput, RemoteError> {
     let url = format!("{}/v1/infer", base_url.trim_end_matches('/'));
-    let req = RemoteInferReq { image_url, title };
     let resp = http
         .post(url)
         .timeout(Duration::from_secs(12))
-        .json(&req)
+        .json(req)
         .send()
         .await
         .map_err(|_| RemoteError::Send)?;
@@ -441,13 +534,20 @@ async fn remote_infer_try(http: &Client, base_url: &str, image_url: &str, title:
         return Err(RemoteError::Status(code));
     }
     let r: RemoteInferResp = resp.json().await.map_err(|_| RemoteError::Parse)?;
-    Ok(engine::EngineOutput { embed_dim: 0, embedding: vec![], caption: r.caption, tags: r.tags })
+    Ok(RemoteOutput {
+        output: engine::EngineOutput { embedding: vec![], caption: r.caption, tags: r.tags },
+        tag_scores: r.tag_scores,
+        alternatives: r.captions,
+        text: r.text,
+    })
 }
 
-async fn remote_infer_failover_backoff(state: &Arc<AppState>, urls: &[String], image_url: &str, title: Option<&str>) -> Result<engine::EngineOutput> {
+async fn remote_infer_failover_backoff(state: &AppState, urls: &[String], req: &CaptionReq, image: &Bytes) -> Result<RemoteOutput> {
     let mut last_err: Option<String> = None;
+    // Build the body once so the image is base64-encoded once, not per endpoint.
+    let body = RemoteInferReq::new(req, image);
     for u in urls {
-        match remote_infer_try(&state.http, u, image_url, title).await {
+        match remote_infer_try(&state.http, u, &body).await {
             Ok(o) => return Ok(o),
             Err(e) => {
                 match e {
@@ -482,14 +582,14 @@ fn rotate_urls(urls: &[String], start: usize) -> Vec<String> {
     out
 }
 
-fn filter_backoff(state: &Arc<AppState>, urls: Vec<String>) -> Vec<String> {
+fn filter_backoff(state: &AppState, urls: Vec<String>) -> Vec<String> {
     if urls.is_empty() { return urls; }
     let now = StdInstant::now();
     let m = state.remote_backoff.lock().unwrap();
     urls.into_iter().filter(|u| m.get(u).map(|&until| now >= until).unwrap_or(true)).collect()
 }
 
-fn mark_backoff(state: &Arc<AppState>, url: &str) {
+fn mark_backoff(state: &AppState, url: &str) {
     let until = StdInstant::now() + std::time::Duration::from_secs(state.remote_backoff_secs);
     let mut m = state.remote_backoff.lock().unwrap();
     m.insert(url.to_string(), until);
@@ -497,102 +597,25 @@ fn mark_backoff(state: &Arc<AppState>, url: &str) {
 
 async fn caption_bulk(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(req): Json<BulkReq>,
-) -> Result<Json<BulkResp>> {
+) -> Response {
+    if req.items.iter().any(|i| i.debug) && let Err(e) = trace::authorize(state.admin_key.as_deref(), &headers) {
+        return e.into_response();
+    }
     state
         .request_count
         .fetch_add(req.items.len() as u64, Ordering::Relaxed);
 
     // Process items concurrently for throughput.
     let mut handles = Vec::with_capacity(req.items.len());
-    let remote_urls = state.remote_infer_urls.clone();
     for item in req.items.into_iter() {
-        let http = state.http.clone();
-        let engine_tx = state.engine_tx.clone();
-        #[cfg(feature = "turbo-ffi")]
-        let decode_limit = state.decode_limit.clone();
-        let model_name = state.model_name;
-        let remote_urls = remote_urls.clone();
-        let state_cl = state.clone();
+        let state = state.clone();
         handles.push(tokio::spawn(async move {
-            // validate URL early
-            if item.image_url.trim().is_empty()
-                || !(item.image_url.starts_with("http://") || item.image_url.starts_with("https://"))
-            {
-                return ItemOutcome::Error(ErrBody { error: "invalid image_url".to_string() });
+            match state.pipeline.run(&state, item).await {
+                Ok(ctx) => ItemOutcome::Ok(Box::new(ctx.into_resp())),
+                Err(e) => ItemOutcome::Error(ErrBody { error: e.to_string() }),
             }
-
-            // Choose remote or local path
-            let eng_out = if !remote_urls.is_empty() {
-                // Distribute requests by hashing the URL to choose the starting endpoint
-                use std::collections::hash_map::DefaultHasher;
-                use std::hash::{Hash, Hasher};
-                let mut hasher = DefaultHasher::new();
-                item.image_url.hash(&mut hasher);
-                let start = (hasher.finish() as usize) % remote_urls.len().max(1);
-                let try_urls = rotate_urls(&remote_urls, start);
-                // No backoff state in this task: simply try in computed order
-                match remote_infer_failover_backoff(&state_cl, &try_urls, &item.image_url, item.product_title.as_deref()).await {
-                    Ok(o) => o,
-                    Err(_) => {
-                        // Fallback to local on error
-                        let bytes = match fetch_bytes(&http, &item.image_url).await { Ok(b) => b, Err(e) => return ItemOutcome::Error(ErrBody { error: e.to_string() }) };
-                        let img = {
-                            let span = tracing::info_span!("caption_bulk", model = model_name);
-                            let _enter = span.enter();
-                            #[cfg(feature = "turbo-ffi")]
-                            {
-                                let _permit = decode_limit.acquire_owned().await.unwrap();
-                                match decode(&bytes).await { Ok(img) => img, Err(e) => return ItemOutcome::Error(ErrBody { error: e.to_string() }) }
-                            }
-                            #[cfg(not(feature = "turbo-ffi"))]
-                            {
-                                match decode_image(bytes.clone()).await { Ok(img) => img, Err(e) => return ItemOutcome::Error(ErrBody { error: e.to_string() }) }
-                            }
-                        };
-                        let (tx1, rx1) = tokio::sync::oneshot::channel();
-                        if let Err(_) = engine_tx.send(engine::Job { image: img, title: item.product_title.clone(), tx: tx1 }).await {
-                            return ItemOutcome::Error(ErrBody { error: "engine unavailable".into() });
-                        }
-                        match rx1.await { Ok(Ok(e)) => e, Ok(Err(e)) => return ItemOutcome::Error(ErrBody { error: e.to_string() }), Err(_) => return ItemOutcome::Error(ErrBody { error: "engine failed".into() }) }
-                    }
-                }
-            } else {
-                // Local path
-                let bytes = match fetch_bytes(&http, &item.image_url).await { Ok(b) => b, Err(e) => return ItemOutcome::Error(ErrBody { error: e.to_string() }) };
-                let img = {
-                    let span = tracing::info_span!("caption_bulk", model = model_name);
-                    let _enter = span.enter();
-                    #[cfg(feature = "turbo-ffi")]
-                    {
-                        let _permit = decode_limit.acquire_owned().await.unwrap();
-                        match decode(&bytes).await { Ok(img) => img, Err(e) => return ItemOutcome::Error(ErrBody { error: e.to_string() }) }
-                    }
-                    #[cfg(not(feature = "turbo-ffi"))]
-                    {
-                        match decode_image(bytes.clone()).await { Ok(img) => img, Err(e) => return ItemOutcome::Error(ErrBody { error: e.to_string() }) }
-                    }
-                };
-                let (tx1, rx1) = tokio::sync::oneshot::channel();
-                if let Err(_) = engine_tx.send(engine::Job { image: img, title: item.product_title.clone(), tx: tx1 }).await {
-                    return ItemOutcome::Error(ErrBody { error: "engine unavailable".into() });
-                }
-                match rx1.await { Ok(Ok(e)) => e, Ok(Err(e)) => return ItemOutcome::Error(ErrBody { error: e.to_string() }), Err(_) => return ItemOutcome::Error(ErrBody { error: "engine failed".into() }) }
-            };
-
-            // Compose response using make_caption template + tags/caption
-            let base = make_caption(&CaptionReq { image_url: item.image_url, product_title: item.product_title.clone() }).unwrap_or(CaptionResp { alt_text: "Product photo".into(), tags: vec![] });
-            let raw = if !eng_out.caption.is_empty() { clean_caption(eng_out.caption.clone()) } else { base.alt_text };
-            let mut alt = refine_alt(item.product_title.as_deref(), &raw, &eng_out.tags);
-            if alt.len() > 125 {
-                let mut cut = 125usize;
-                if let Some(pos) = alt[..125].rfind(' ') { cut = pos; }
-                alt.truncate(cut);
-                for conn in [" with", " and", " of", " on", " in", " at", " for", " to", " by"] {
-                    if alt.ends_with(conn) { alt.truncate(alt.len() - conn.len()); break; }
-                }
-            }
-            ItemOutcome::Ok(CaptionResp { alt_text: alt, tags: eng_out.tags })
         }));
     }
 
@@ -603,7 +626,7 @@ async fn caption_bulk(
             Err(_) => out.push(ItemOutcome::Error(ErrBody { error: "task join failed".into() })),
         }
     }
-    Ok(Json(BulkResp { results: out }))
+    Json(BulkResp { results: out }).into_response()
 }
 
 #[tokio::main]
@@ -644,11 +667,25 @@ async fn main() {
         .join("../models/clip/onnx32-open_clip-ViT-B-16-openai-visual.onnx")
         .to_string_lossy()
         .into_owned();
-    let engine = engine::spawn(
-        permits,
-        worker_count,
-        &clip_vis,
-    );
+    // A missing model shouldn't keep dev machines and CI images from starting.
+    let engine = match engine::spawn(permits, worker_count, &clip_vis) {
+        Ok(engine) => Some(engine),
+        Err(e) => {
+            tracing::warn!(err = %e, "no local model; captions come from remote endpoints or templates");
+            None
+        }
+    };
+
+    let clip_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../models/clip");
+    let clip_textual = clip_dir.join("onnx32-open_clip-ViT-B-16-openai-textual.onnx").to_string_lossy().into_owned();
+    let clip_tokenizer = clip_dir.join("tokenizer.json").to_string_lossy().into_owned();
+    let text_encoder = match clip_text::TextEncoder::load(&clip_textual, &clip_tokenizer) {
+        Ok(enc) => Some(Arc::new(enc)),
+        Err(e) => {
+            info!(err = %e, "no CLIP text encoder; reranking disabled");
+            None
+        }
+    };
 
     // Log key toggles for easier debugging of env mismatches
     info!(
@@ -658,35 +695,77 @@ async fn main() {
         blip_kv = %std::env::var("CAPTIONER_BLIP_KV").unwrap_or_else(|_| "(default)".into()),
         blip_prefix = %std::env::var("CAPTIONER_BLIP_PREFIX").unwrap_or_else(|_| "(default)".into()),
         cors_mode = %std::env::var("CAPTIONER_CORS_PERMISSIVE").unwrap_or_else(|_| "(default)".into()),
+        file_roots = %std::env::var("CAPTIONER_FILE_ROOTS").unwrap_or_else(|_| "(disabled)".into()),
+        s3_endpoint = %std::env::var("CAPTIONER_S3_ENDPOINT").unwrap_or_else(|_| "(default)".into()),
+        s3_buckets = %std::env::var("CAPTIONER_S3_BUCKETS").unwrap_or_else(|_| "(none)".into()),
+        cdn_rules = %std::env::var("CAPTIONER_CDN_RULES").unwrap_or_else(|_| "(shopify only)".into()),
+        alt_max_len = %std::env::var("CAPTIONER_ALT_MAX_LEN").unwrap_or_else(|_| "(default)".into()),
+        alt_speech = %std::env::var("CAPTIONER_ALT_SPEECH").unwrap_or_else(|_| "(default)".into()),
+        alt_people = %std::env::var("CAPTIONER_ALT_PEOPLE").unwrap_or_else(|_| "(default)".into()),
+        alt_policies = %std::env::var("CAPTIONER_ALT_POLICIES").unwrap_or_else(|_| "(none)".into()),
+        taxonomy = %std::env::var("CAPTIONER_TAXONOMY").unwrap_or_else(|_| "(builtin)".into()),
+        locales = %std::env::var("CAPTIONER_LOCALES").unwrap_or_else(|_| "(builtin)".into()),
+        rules_dir = %std::env::var("CAPTIONER_RULES_DIR").unwrap_or_else(|_| "(none)".into()),
+        rules_reload_secs = %std::env::var("CAPTIONER_RULES_RELOAD_SECS").unwrap_or_else(|_| "(default)".into()),
+        fetch_allow_hosts = %std::env::var("CAPTIONER_FETCH_ALLOW_HOSTS").unwrap_or_else(|_| "(any public)".into()),
         remote_endpoints = %std::env::var("CAPTIONER_REMOTE_INFER_URLS").unwrap_or_else(|_| "(none)".into()),
         remote_backoff_secs = %std::env::var("CAPTIONER_REMOTE_BACKOFF_SECS").unwrap_or_else(|_| "(default)".into()),
+        rerank_margin = %std::env::var("CAPTIONER_RERANK_MARGIN").unwrap_or_else(|_| "(default)".into()),
+        long_desc_max_len = %std::env::var("CAPTIONER_LONG_DESC_MAX_LEN").unwrap_or_else(|_| "(default)".into()),
+        fallback = %std::env::var("CAPTIONER_FALLBACK").unwrap_or_else(|_| "(default)".into()),
+        admin_key = if std::env::var("CAPTIONER_ADMIN_KEY").is_ok_and(|k| !k.is_empty()) { "set" } else { "(unset)" },
         "env configured"
     );
 
+    // S3 endpoints are operator-configured (often a private MinIO), so they use the
+    // trusted client rather than the policy-guarded one.
+    let s3 = sources::S3Config::from_env().expect("s3 config").map(|cfg| sources::S3Source::new(http.clone(), cfg).expect("s3 config"));
+    let fetcher = fetch::Fetcher::new(url_policy::UrlPolicy::from_env())
+        .expect("fetch client")
+        .with_cdn(cdn::CdnRewriter::from_env())
+        .with_files(sources::FileSource::from_env())
+        .with_s3(s3);
+    let taxonomy = taxonomy::Taxonomy::from_env().expect("taxonomy");
+    let locales = locale::Locales::from_env().expect("locales");
+    let alt_policies = alt_policy::PolicyBook::from_env().expect("alt text policies");
+    let rules = rules::RuleBook::from_env().expect("rewrite rules");
+    let pipeline = pipeline::CaptionPipeline::from_env().expect("caption pipeline");
+    info!(stages = ?pipeline.stage_names(), "caption pipeline");
+
     let state = Arc::new(AppState {
         model_name: "onnx32-open_clip-ViT-B-16-openai-visual",
         request_count: AtomicU64::new(0),
         http,
+        fetcher,
+        taxonomy,
+        locales,
+        alt_policies,
+        rules,
+        pipeline,
         remote_infer_urls: {
             let mut v: Vec<String> = std::env::var("CAPTIONER_REMOTE_INFER_URLS")
                 .ok()
                 .map(|s| s.split(',').map(|p| p.trim().to_string()).filter(|x| !x.is_empty()).collect())
-                .unwrap_or_else(Vec::new);
-            if v.is_empty() {
-                if let Ok(u1) = std::env::var("CAPTIONER_REMOTE_INFER_URL") {
-                    let u1 = u1.trim().to_string();
-                    if !u1.is_empty() { v.push(u1); }
-                }
+                .unwrap_or_default();
+            if v.is_empty()
+                && let Ok(u1) = std::env::var("CAPTIONER_REMOTE_INFER_URL")
+            {
+                let u1 = u1.trim().to_string();
+                if !u1.is_empty() { v.push(u1); }
             }
             v
         },
         remote_rr: AtomicUsize::new(0),
         remote_backoff: Mutex::new(HashMap::new()),
         remote_backoff_secs: std::env::var("CAPTIONER_REMOTE_BACKOFF_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(300),
+        admin_key: std::env::var("CAPTIONER_ADMIN_KEY").ok().filter(|k| !k.is_empty()),
         #[cfg(feature = "turbo-ffi")]
         decode_limit: Arc::new(Semaphore::new(permits)),
-        engine_tx: engine.sender(),
+        engine_tx: engine.as_ref().map(engine::Engine::sender),
+        text_encoder,
     });
+    let rules_reload = std::env::var("CAPTIONER_RULES_RELOAD_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(rules::DEFAULT_RELOAD_SECS);
+    rules::RuleBook::watch(state.clone(), Duration::from_secs(rules_reload.max(1)));
 
     // CORS: default to permissive for development
     let cors = CorsLayer::permissive();
@@ -703,8 +782,13 @@ async fn main() {
 
     let app = Router::new()
         .route("/health", get(health))
-        .route("/v1/caption", post(caption))
-        .route("/v1/bulk", post(caption_bulk))
+        .route("/v1/caption", post(caption).layer(DefaultBodyLimit::max(max_upload_bytes())))
+        .route("/v1/bulk", post(caption_bulk).layer(DefaultBodyLimit::max(max_upload_bytes())))
+        .route("/v1/seo", post(seo::seo).layer(DefaultBodyLimit::max(max_upload_bytes())))
+        .route("/v1/lint", post(lint::lint))
+        .route("/v1/rules/dry-run", post(rules::dry_run))
+        // Python worker contract (apps/worker), for WORKER_URL
+        .route("/caption", post(compat::caption))
         .with_state(state)
         .layer(layers)
         // Make CORS the outermost layer so preflights and errors include headers
@@ -719,6 +803,14 @@ async fn main() {
         .unwrap();
 }
 
+// Uploads (and bulk bodies with data: URIs) exceed axum's 2 MB default body limit.
+fn max_upload_bytes() -> usize {
+    std::env::var("CAPTIONER_MAX_UPLOAD_BYTES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(20 * 1024 * 1024)
+}
+
 async fn shutdown_signal() {
     let _ = signal::ctrl_c().await;
     println!("\nshutting down...");
@@ -739,13 +831,21 @@ mod tests {
             .with_state(state)
     }
 
+    fn refine_alt(tax: &taxonomy::Taxonomy, product_title: Option<&str>, current_alt: &str, tags: &[String]) -> String {
+        let req = CaptionReq { product_title: product_title.map(str::to_string), ..Default::default() };
+        refine_alt_traced(tax, &req, current_alt, tags).0
+    }
+
     fn dummy_state() -> Arc<AppState> {
+        dummy_state_with(pipeline::CaptionPipeline::standard())
+    }
+
+    pub(crate) fn dummy_state_with(pipeline: pipeline::CaptionPipeline) -> Arc<AppState> {
         // Engine stub that immediately replies with fixed tags
         let (tx, mut rx) = mpsc::channel::<engine::Job>(1);
         tokio::spawn(async move {
             while let Some(job) = rx.recv().await {
                 let _ = job.tx.send(Ok(engine::EngineOutput {
-                    embed_dim: 1,
                     embedding: vec![0.0],
                     caption: String::new(),
                     tags: vec!["red".into(), "shoe".into()],
@@ -757,31 +857,46 @@ mod tests {
             model_name: "test-model",
             request_count: AtomicU64::new(0),
             http: Client::new(),
+            fetcher: fetch::Fetcher::new(url_policy::UrlPolicy::default()).unwrap(),
+            taxonomy: taxonomy::Taxonomy::builtin(),
+            locales: locale::Locales::builtin(),
+            alt_policies: alt_policy::PolicyBook::default(),
+            rules: rules::RuleBook::default(),
+            pipeline,
             remote_infer_urls: Vec::new(),
             remote_rr: AtomicUsize::new(0),
             remote_backoff: Mutex::new(HashMap::new()),
             remote_backoff_secs: 60,
+            admin_key: Some("test-admin-key".into()),
             #[cfg(feature = "turbo-ffi")]
             decode_limit: Arc::new(Semaphore::new(2)),
-            engine_tx: tx,
+            engine_tx: Some(tx),
+            text_encoder: None,
         })
     }
 
     #[test]
     fn make_caption_validates() {
-        let empty = CaptionReq { image_url: "".into(), product_title: None };
-        assert!(matches!(make_caption(&empty), Err(ApiError::BadRequest(_))));
+        let empty = CaptionReq { image_url: "".into(), product_title: None, ..Default::default() };
+        assert!(matches!(make_caption(&empty, &Default::default()), Err(ApiError::BadRequest(_))));
 
-        let bad_scheme = CaptionReq { image_url: "ftp://example.com/x.jpg".into(), product_title: None };
-        assert!(matches!(make_caption(&bad_scheme), Err(ApiError::BadRequest(_))));
+        let bad_scheme = CaptionReq { image_url: "ftp://example.com/x.jpg".into(), product_title: None, ..Default::default() };
+        assert!(matches!(make_caption(&bad_scheme, &Default::default()), Err(ApiError::BadRequest(_))));
     }
 
     #[test]
     fn make_caption_truncates() {
         let long_title = "a".repeat(200);
-        let req = CaptionReq { image_url: "https://x".into(), product_title: Some(long_title) };
-        let out = make_caption(&req).expect("ok");
+        let req = CaptionReq { image_url: "https://x".into(), product_title: Some(long_title), ..Default::default() };
+        let out = make_caption(&req, &Default::default()).expect("ok");
         assert!(out.alt_text.len() <= 125);
+
+        // Multi-byte titles near the limit used to panic on a byte slice.
+        let policy = alt_policy::AltTextPolicy::new(125, alt_policy::Limit::Hard);
+        let req = CaptionReq { image_url: "https://x".into(), product_title: Some("Crème brûlée ".repeat(12)), ..Default::default() };
+        let out = make_caption(&req, &policy).expect("ok");
+        assert!(out.alt_text.chars().count() <= 125);
+        assert!(out.alt_text.ends_with(" Crème"), "{}", out.alt_text);
     }
 
     #[tokio::test]
@@ -815,6 +930,7 @@ mod tests {
         let bytes = body::to_bytes(txt.into_body(), usize::MAX).await.unwrap();
         let s = String::from_utf8(bytes.to_vec()).unwrap();
         assert!(s.contains("requests=2"), "health body: {}", s);
+        assert!(s.contains("mode=local"), "health body: {}", s);
     }
 
     #[tokio::test]
@@ -835,4 +951,323 @@ mod tests {
         let v: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
         assert_eq!(v["error"], "image_url required");
     }
+
+    pub(crate) const SAMPLE_JPG: &[u8] = include_bytes!("../tests/fixtures/sample.jpg");
+
+    async fn post_caption(app: Router, content_type: &str, uri: &str, body: Vec<u8>) -> (StatusCode, serde_json::Value) {
+        let resp = app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", content_type)
+                .body(Body::from(body))
+                .unwrap()
+        ).await.unwrap();
+        let status = resp.status();
+        let bytes = body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn caption_accepts_multipart_upload() {
+        let app = build_test_app(dummy_state());
+        let mut body = Vec::new();
+        body.extend_from_slice(b"--XBOUNDARY\r\nContent-Disposition: form-data; name=\"product_title\"\r\n\r\nRed Runner\r\n");
+        body.extend_from_slice(b"--XBOUNDARY\r\nContent-Disposition: form-data; name=\"image\"; filename=\"sample.jpg\"\r\nContent-Type: image/jpeg\r\n\r\n");
+        body.extend_from_slice(SAMPLE_JPG);
+        body.extend_from_slice(b"\r\n--XBOUNDARY--\r\n");
+        let (status, v) = post_caption(app, "multipart/form-data; boundary=XBOUNDARY", "/v1/caption", body).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["tags"], serde_json::json!(["red", "shoe"]));
+    }
+
+    #[tokio::test]
+    async fn caption_accepts_raw_image_body() {
+        let app = build_test_app(dummy_state());
+        let (status, v) = post_caption(app, "image/jpeg", "/v1/caption?product_title=Red%20Runner", SAMPLE_JPG.to_vec()).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert!(!v["alt_text"].as_str().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn caption_accepts_data_uri() {
+        let app = build_test_app(dummy_state());
+        let uri = format!("data:image/jpeg;base64,{}", base64::engine::general_purpose::STANDARD.encode(SAMPLE_JPG));
+        let body = serde_json::json!({"image_url": uri, "product_title": "Red Runner"});
+        let (status, v) = post_caption(app.clone(), "application/json", "/v1/caption", serde_json::to_vec(&body).unwrap()).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+
+        let body = serde_json::json!({"image_url": "data:text/plain;base64,aGk="});
+        let (status, v) = post_caption(app, "application/json", "/v1/caption", serde_json::to_vec(&body).unwrap()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(v["error"], "data uri must be an image");
+    }
+
+    // The reference server's InferReq fields, read from its source: (name, required).
+    fn server_infer_fields() -> Vec<(String, bool)> {
+        let src = include_str!("../../../../tools/blip_infer_server/server.py");
+        let (_, body) = src.split_once("class InferReq(BaseModel):\n").expect("InferReq in server.py");
+        body.lines()
+            .take_while(|l| l.is_empty() || l.starts_with(' '))
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_once(':').map(|(name, rest)| (name.trim().to_string(), !rest.contains('='))))
+            .collect()
+    }
+
+    #[test]
+    fn remote_infer_req_matches_the_reference_server() {
+        let fields = server_infer_fields();
+        let req = CaptionReq { product_title: Some("Red Runner".into()), candidates: Some(3), ..Default::default() };
+        for req in [req, CaptionReq::default()] {
+            let v = serde_json::to_value(RemoteInferReq::new(&req, &Bytes::from_static(SAMPLE_JPG))).unwrap();
+            let sent = v.as_object().unwrap();
+            for key in sent.keys() {
+                assert!(fields.iter().any(|(f, _)| f == key), "server.py InferReq has no {key:?}: {fields:?}");
+            }
+            for (f, required) in &fields {
+                assert!(!required || sent.contains_key(f), "server.py requires {f:?}");
+            }
+            assert_eq!(base64::engine::general_purpose::STANDARD.decode(sent["image_b64"].as_str().unwrap()).unwrap(), SAMPLE_JPG);
+        }
+        assert!(fields.contains(&("image_b64".to_string(), false)) && fields.contains(&("image_url".to_string(), false)));
+    }
+
+    #[tokio::test]
+    async fn caption_applies_request_alt_policy() {
+        let app = build_test_app(dummy_state());
+        let body = serde_json::json!({
+            "image_url": format!("data:image/jpeg;base64,{}", base64::engine::general_purpose::STANDARD.encode(SAMPLE_JPG)),
+            "product_title": "Red Runner Shoe",
+            "shop": "acme.myshopify.com",
+            "alt_policy": {"max_len": 5, "limit": "hard"}
+        });
+        let (status, v) = post_caption(app, "application/json", "/v1/caption", serde_json::to_vec(&body).unwrap()).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["alt_text"], "Red");
+    }
+
+    #[tokio::test]
+    async fn caption_debug_trace_requires_admin_key() {
+        let app = build_test_app(dummy_state());
+        let body = serde_json::json!({
+            "image_url": format!("data:image/jpeg;base64,{}", base64::engine::general_purpose::STANDARD.encode(SAMPLE_JPG)),
+            "product_title": "Red Runner Shoe",
+            "debug": true
+        });
+        let body = serde_json::to_vec(&body).unwrap();
+        let (status, v) = post_caption(app.clone(), "application/json", "/v1/caption", body.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{v}");
+
+        let resp = app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/v1/caption")
+                .header("content-type", "application/json")
+                .header("x-admin-key", "test-admin-key")
+                .body(Body::from(body))
+                .unwrap()
+        ).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let v: serde_json::Value = serde_json::from_slice(&body::to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(v["alt_text"], "Red shoe");
+        let d = &v["debug"];
+        assert_eq!(d["cleaned_caption"], "Red Runner Shoe on a plain background");
+        assert_eq!(d["tags"][0], serde_json::json!({"tag": "red", "score": null}));
+        assert_eq!(d["refine"]["branch"], "composed");
+        assert_eq!(d["refine"]["category"], "shoe");
+        assert_eq!(d["refine"]["category_source"], "title");
+        assert_eq!(d["refine"]["attributes"][0], serde_json::json!({"attribute": "color", "value": "red", "source": "tag"}));
+        let stages: Vec<&str> = d["stages"].as_array().unwrap().iter().map(|s| s["stage"].as_str().unwrap()).collect();
+        assert_eq!(stages, ["validate", "fetch", "decode", "infer", "clean", "refine", "rerank", "people", "existing_alt", "localize", "speech", "rules", "policy", "postprocess", "candidates", "long_description"]);
+    }
+
+    #[test]
+    fn refine_alt_trusts_shop_product_context() {
+        let tax = taxonomy::Taxonomy::builtin();
+        let mut req = CaptionReq {
+            product_title: Some("Black Diamond Alpine Jacket".into()),
+            vendor: Some("Black Diamond".into()),
+            tags: tags(&["wool", "Winter"]),
+            variant: VariantOptions { color: Some("Midnight".into()), ..Default::default() },
+            ..Default::default()
+        };
+        let (alt, tr) = refine_alt_traced(&tax, &req, "a woman in a red jacket", &tags(&["red", "nylon"]));
+        assert_eq!(alt, "Midnight wool jacket");
+        let sources: Vec<(&str, trace::Source)> = tr.attributes.iter().map(|a| (a.attribute.as_str(), a.source)).collect();
+        assert_eq!(sources, [("color", trace::Source::Variant), ("material", trace::Source::ProductTag)]);
+        req.include_brand = true;
+        assert_eq!(refine_alt_traced(&tax, &req, "", &[]).0, "Black Diamond midnight wool jacket");
+
+        let req = CaptionReq {
+            product_title: Some("ACME Essentials".into()),
+            vendor: Some("ACME".into()),
+            product_type: Some("Candle".into()),
+            tags: tags(&["Lavender", "8oz"]),
+            ..Default::default()
+        };
+        let (alt, tr) = refine_alt_traced(&tax, &req, "", &[]);
+        assert_eq!(alt, "Lavender-scented 8-oz candle");
+        assert_eq!(tr.category_source, Some(trace::Source::ProductType));
+        let policy = alt_policy::AltTextPolicy::default();
+        let req = CaptionReq { image_url: "https://x".into(), ..req };
+        assert_eq!(make_caption(&req, &policy).unwrap().alt_text, "Essentials on a plain background");
+    }
+
+    #[test]
+    fn refine_trace_records_short_output_guard() {
+        let tax = taxonomy::Taxonomy::builtin();
+        // Coffee has no color attribute, so the color only comes in through the guard.
+        let req = CaptionReq { product_title: Some("Black Coffee".into()), ..Default::default() };
+        let (alt, tr) = refine_alt_traced(&tax, &req, "", &[]);
+        assert_eq!(alt, "Black coffee");
+        assert_eq!(tr.branch, trace::Branch::ShortOutputGuard);
+        assert_eq!((tr.attributes[0].value.as_str(), tr.attributes[0].source), ("black", trace::Source::Title));
+        let (_, tr) = refine_alt_traced(&tax, &CaptionReq::default(), "a person smiling", &[]);
+        assert_eq!(tr.branch, trace::Branch::NoCategory);
+        assert!(tr.category.is_none());
+    }
+
+    fn tags(t: &[&str]) -> Vec<String> {
+        t.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn refine_alt_composes_in_english_order() {
+        let tax = taxonomy::Taxonomy::builtin();
+        let cases: &[(Option<&str>, &[&str], &str)] = &[
+            (None, &["black", "leather", "shoe", "buckle"], "Black leather shoe with a buckle"),
+            (None, &["navy", "wool", "sweater", "long sleeve", "v-neck"], "Navy wool long-sleeve v-neck sweater"),
+            (None, &["red", "sequin", "dress"], "Red sequined dress"),
+            (None, &["white", "crop", "top"], "White crop top"),
+            (None, &["black", "stainless", "bottle", "insulated"], "Black stainless steel insulated bottle"),
+            (None, &["glossy", "pink", "case"], "Glossy pink case"),
+            (Some("Canvas Tote"), &["beige"], "Beige canvas tote"),
+            (None, &["jeans"], "Jeans"),
+        ];
+        for (title, t, want) in cases {
+            assert_eq!(refine_alt(&tax, *title, "", &tags(t)), *want, "tags {t:?}");
+        }
+    }
+
+    // Every combination of a category's attribute types, cycling through every value of every
+    // vocabulary, must come out sentence-cased with modifiers in slot order before the noun and
+    // "with" details after it.
+    #[test]
+    fn refine_alt_orders_every_vocabulary_combination() {
+        let tax = taxonomy::Taxonomy::builtin();
+        let mut seen: HashMap<&str, std::collections::HashSet<&str>> = HashMap::new();
+        for (ci, cat) in tax.categories().iter().enumerate() {
+            let attrs: Vec<(&str, &taxonomy::Attribute)> =
+                tax.attributes_of(cat).filter(|(_, a)| !a.is_measure()).collect();
+            // Each attribute appears in half the masks; repeat until every value has had a turn.
+            let half = 1usize << attrs.len().saturating_sub(1);
+            let rounds = attrs.iter().map(|(_, a)| a.values().len().div_ceil(half)).max().unwrap_or(1);
+            let mut turns = vec![0usize; attrs.len()];
+            for mask in (0..rounds).flat_map(|_| 0u32..(1 << attrs.len())) {
+                let mut t = vec![cat.name.clone()];
+                let mut picked = Vec::new();
+                for (k, (name, attr)) in attrs.iter().enumerate() {
+                    if mask & (1 << k) != 0 {
+                        let values = attr.values();
+                        let w = values[(ci + turns[k]) % values.len()];
+                        turns[k] += 1;
+                        t.push(w.to_string());
+                        picked.push((*attr, w));
+                        seen.entry(name).or_default().insert(w);
+                    }
+                }
+                let out = refine_alt(&tax, None, "", &t);
+                let ctx = format!("tags {t:?} -> {out:?}");
+                assert!(out.chars().next().is_some_and(|c| !c.is_lowercase()), "{ctx}");
+                assert!(!out.contains("  "), "{ctx}");
+
+                let lower = out.to_lowercase();
+                let (head, tail) = lower.split_once(" with ").unwrap_or((&lower, ""));
+                let toks: Vec<&str> = head.split([' ', '-']).collect();
+                let pos = |phrase: &str| {
+                    let first = phrase.split([' ', '-']).next().unwrap();
+                    toks.iter().position(|tk| *tk == first)
+                };
+                let noun_pos = toks.len() - cat.name.split([' ', '-']).count();
+                assert_eq!(pos(&cat.name), Some(noun_pos), "{ctx}");
+                let mut placed = Vec::new();
+                for (attr, w) in picked {
+                    let form = attr.form_of(w);
+                    match attr.slot_of(w) {
+                        None => assert!(tail.contains(form), "{ctx}"),
+                        Some(slot) => {
+                            let p = pos(form).expect(&ctx);
+                            assert!(p < noun_pos, "{ctx}");
+                            placed.push((slot, p));
+                        }
+                    }
+                }
+                for &(sa, pa) in &placed {
+                    for &(sb, pb) in &placed {
+                        if sa < sb { assert!(pa < pb, "{ctx}"); }
+                    }
+                }
+            }
+        }
+        for (name, attr) in tax.categories().iter().flat_map(|c| tax.attributes_of(c)) {
+            if !attr.is_measure() {
+                assert_eq!(seen[name].len(), attr.values().len(), "every value of {name} exercised");
+            }
+        }
+    }
+
+    #[test]
+    fn refine_alt_uses_category_specific_attributes() {
+        let tax = taxonomy::Taxonomy::builtin();
+        let cases: &[(Option<&str>, &[&str], &str)] = &[
+            (Some("Lavender Soy Candle 8 oz"), &[], "Lavender-scented 8-oz soy candle"),
+            (Some("Summit Bottle 750ml"), &["black", "insulated"], "750-ml black insulated bottle"),
+            (Some("Oak Extendable Dining Table"), &[], "Oak extendable table"),
+            (Some("Velvet Couch"), &["green", "tufted"], "Green tufted sofa"),
+            (Some("Organic Dark Roast Coffee Beans"), &[], "Dark-roast organic coffee"),
+            (Some("Hydrating Face Serum 1 fl oz"), &[], "1-fl-oz hydrating serum"),
+            // Sleeves and necklines only apply to apparel
+            (Some("Trail Shoe"), &["long sleeve", "v-neck", "blue"], "Blue shoe"),
+        ];
+        for (title, t, want) in cases {
+            assert_eq!(refine_alt(&tax, *title, "", &tags(t)), *want, "title {title:?}");
+        }
+    }
+
+    // Substring matching used to find "man" in "German", "tan" in "tank top" and "top" in "laptop".
+    #[test]
+    fn refine_alt_ignores_substring_false_positives() {
+        let tax = taxonomy::Taxonomy::builtin();
+        let colors = tax.attribute("color").unwrap().values();
+        let category = |text: &str| tax.category_in_text(text).map(|c| c.name.clone());
+
+        // "German"/"manual" are not people: a caption with a color and product stays as is.
+        let alt = "black German shepherd mug next to a manual";
+        assert_eq!(refine_alt(&tax, None, alt, &[]), alt);
+        assert!(!contains_any("manual coffee grinder", PEOPLE));
+        assert!(contains_any("a man holding a mug", PEOPLE));
+
+        // "tank top" has no "tan"; the color comes from the tags instead.
+        assert_eq!(pick_from_text(&colors, "olive tank top"), Some("olive"));
+        assert_eq!(pick_from_text(&colors, "tank top"), None);
+        assert_eq!(refine_alt(&tax, Some("Tank Top"), "", &tags(&["white"])), "White top");
+
+        // "laptop" is a laptop, not a top.
+        assert_eq!(category("laptop sleeve").as_deref(), Some("laptop"));
+        assert_eq!(refine_alt(&tax, Some("Slim Laptop"), "a silver laptop on a desk", &[]), "Silver laptop");
+    }
+
+    #[test]
+    fn refine_alt_matches_variants_and_plurals() {
+        let tax = taxonomy::Taxonomy::builtin();
+        let category = |text: &str| tax.category_in_text(text).map(|c| c.name.clone());
+        assert_eq!(category("Red Tee Shirt").as_deref(), Some("t-shirt"));
+        assert_eq!(category("Graphic T-Shirts").as_deref(), Some("t-shirt"));
+        assert_eq!(category("Running Shoes").as_deref(), Some("shoe"));
+        let sleeves = tax.attribute("sleeve").unwrap().values();
+        assert_eq!(pick_first(&sleeves, &tags(&["Long-Sleeve"])), Some("long sleeve"));
+        assert_eq!(refine_alt(&tax, Some("Everyday Tee"), "a woman wearing a red tee", &[]), "Red t-shirt");
+        assert_eq!(refine_alt(&tax, None, "a man wearing a watch", &[]), "Watch");
+    }
 }
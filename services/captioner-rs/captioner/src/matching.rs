@@ -0,0 +1,205 @@
+// Token-aware vocabulary matching. Text and vocabulary phrases are split into Unicode
+// words, lowercased and reduced to a simple singular stem, and phrases match as whole
+// n-grams. So "man" no longer matches "German", "tan" no longer matches "tank top", while
+// "T-Shirts", "tee shirt" and "tshirt" all match "t-shirt".
+
+use unicode_segmentation::UnicodeSegmentation;
+
+// Spelling variants that tokenization alone does not unify.
+const ALIASES: &[(&str, &[&str])] = &[
+    ("t-shirt", &["tee shirt", "tee"]),
+    ("off shoulder", &["off the shoulder"]),
+    ("3/4 sleeve", &["three quarter sleeve"]),
+    ("sweatshirt", &["sweat shirt"]),
+];
+
+pub struct Tokens {
+    toks: Vec<String>,
+}
+
+impl Tokens {
+    pub fn new(text: &str) -> Self {
+        Tokens { toks: tokenize(text) }
+    }
+
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.toks.get(i).map(String::as_str)
+    }
+
+    pub fn contains(&self, phrase: &str) -> bool {
+        !self.find_all(phrase).is_empty()
+    }
+
+    // Every `[start, end)` token span where `phrase` (or one of its variants) occurs.
+    pub fn find_all(&self, phrase: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        for form in phrase_forms(phrase) {
+            let n = form.len();
+            if n == 0 || n > self.toks.len() {
+                continue;
+            }
+            for i in 0..=self.toks.len() - n {
+                if self.toks[i..i + n] == form[..] && !spans.contains(&(i, i + n)) {
+                    spans.push((i, i + n));
+                }
+            }
+        }
+        spans.sort_unstable();
+        spans
+    }
+}
+
+// True when both strings name the same phrase ("T-Shirts" vs "t-shirt").
+pub fn same_phrase(a: &str, b: &str) -> bool {
+    let ta = tokenize(a);
+    phrase_forms(b).contains(&ta) || tokenize(b) == joined(&ta)
+}
+
+// Remove every occurrence of the given phrases, keeping the remaining words in order.
+pub fn remove_phrases(text: &str, phrases: &[&str]) -> String {
+    let words: Vec<&str> = text.unicode_words().collect();
+    let toks = Tokens { toks: words.iter().map(|w| stem(&w.to_lowercase())).collect() };
+    let mut drop = vec![false; words.len()];
+    for p in phrases {
+        for (s, e) in toks.find_all(p) {
+            drop[s..e].iter_mut().for_each(|d| *d = true);
+        }
+    }
+    words
+        .iter()
+        .zip(drop)
+        .filter(|(_, d)| !d)
+        .map(|(w, _)| *w)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Remove a phrase (a brand name, say) while keeping the rest of the text as written.
+pub fn strip_phrase(text: &str, phrase: &str) -> String {
+    let words: Vec<(usize, &str)> = text.unicode_word_indices().collect();
+    let toks = Tokens { toks: words.iter().map(|(_, w)| stem(&w.to_lowercase())).collect() };
+    let mut out = String::new();
+    let mut at = 0;
+    for (s, e) in toks.find_all(phrase) {
+        let (start, (end_at, end_word)) = (words[s].0, words[e - 1]);
+        if start >= at {
+            out.push_str(&text[at..start]);
+            at = end_at + end_word.len();
+        }
+    }
+    out.push_str(&text[at..]);
+    let out = out.split_whitespace().collect::<Vec<_>>().join(" ");
+    // "ACME - Widget" and "Widget | ACME" leave a separator behind
+    out.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '–' | '—' | '|' | ',' | ':')).to_string()
+}
+
+// Replace whole-word occurrences of `phrase`, ignoring case but not plurals or aliases: shop
+// rewrite rules say exactly which words they mean.
+pub fn replace_words(text: &str, phrase: &str, with: &str) -> String {
+    let words: Vec<(usize, &str)> = text.unicode_word_indices().collect();
+    let want: Vec<String> = phrase.unicode_words().map(str::to_lowercase).collect();
+    let n = want.len();
+    let mut out = String::new();
+    let mut at = 0;
+    let mut i = 0;
+    while n > 0 && i + n <= words.len() {
+        if words[i..i + n].iter().zip(&want).all(|((_, w), p)| w.to_lowercase() == *p) {
+            let (end_at, end_word) = words[i + n - 1];
+            out.push_str(&text[at..words[i].0]);
+            out.push_str(with);
+            at = end_at + end_word.len();
+            i += n;
+        } else {
+            i += 1;
+        }
+    }
+    out.push_str(&text[at..]);
+    out
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase().unicode_words().map(stem).collect()
+}
+
+// The phrase itself, its variants, and the closed-up spelling of multi-word forms
+// ("v-neck" → "vneck", "crew neck" → "crewneck").
+fn phrase_forms(phrase: &str) -> Vec<Vec<String>> {
+    let mut forms = vec![tokenize(phrase)];
+    let key = phrase.to_lowercase();
+    for (canon, variants) in ALIASES {
+        if *canon == key {
+            forms.extend(variants.iter().map(|v| tokenize(v)));
+        }
+    }
+    let closed: Vec<Vec<String>> = forms.iter().filter(|f| f.len() > 1).map(|f| joined(f)).collect();
+    forms.extend(closed);
+    forms
+}
+
+fn joined(toks: &[String]) -> Vec<String> {
+    vec![stem(&toks.concat())]
+}
+
+// Minimal English singularization, applied identically to text and vocabulary.
+fn stem(word: &str) -> String {
+    let w = word.trim_end_matches("'s").trim_end_matches("\u{2019}s");
+    let n = w.chars().count();
+    if n <= 3 {
+        return w.to_string();
+    }
+    if let Some(base) = w.strip_suffix("ies") {
+        return format!("{base}y");
+    }
+    for suffix in ["sses", "ches", "shes", "xes"] {
+        if w.ends_with(suffix) {
+            return w[..w.len() - 2].to_string();
+        }
+    }
+    if w.ends_with('s') && !w.ends_with("ss") && !w.ends_with("us") && !w.ends_with("is") {
+        return w[..w.len() - 1].to_string();
+    }
+    w.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_only_whole_words() {
+        let t = Tokens::new("German shepherd on a manual coffee grinder");
+        assert!(!t.contains("man"));
+        assert!(!Tokens::new("olive tank top").contains("tan"));
+        assert!(!Tokens::new("laptop sleeve").contains("top"));
+        assert!(Tokens::new("laptop sleeve").contains("laptop"));
+        assert!(!Tokens::new("womenswear").contains("women"));
+        assert!(Tokens::new("Two women, smiling").contains("women"));
+    }
+
+    #[test]
+    fn matches_variants_plurals_and_phrases() {
+        for text in ["Red T-Shirt", "red t shirt", "red tee shirt", "red tshirt", "Red T-Shirts", "a red tee"] {
+            assert!(Tokens::new(text).contains("t-shirt"), "{text}");
+        }
+        assert!(Tokens::new("leather shoes").contains("shoe"));
+        assert!(Tokens::new("silk dresses").contains("dress"));
+        assert!(Tokens::new("V Neck sweater").contains("v-neck"));
+        assert!(Tokens::new("crewneck sweater").contains("crew neck"));
+        assert!(Tokens::new("off-the-shoulder top").contains("off shoulder"));
+        assert!(Tokens::new("women's boots").contains("boot"));
+        assert_eq!(Tokens::new("black long sleeve shirt").find_all("long sleeve"), vec![(1, 3)]);
+    }
+
+    #[test]
+    fn same_phrase_and_removal() {
+        assert!(same_phrase("T-Shirts", "t-shirt"));
+        assert!(same_phrase("crewneck", "crew neck"));
+        assert!(!same_phrase("shirt", "t-shirt"));
+        assert_eq!(remove_phrases("a man wearing a manual watch", &["man", "wearing"]), "a a manual watch");
+        assert_eq!(strip_phrase("ACME Widget, 12oz", "acme"), "Widget, 12oz");
+        assert_eq!(strip_phrase("Trail Runner 3/4 Sleeve Tee - Black Diamond", "Black Diamond"), "Trail Runner 3/4 Sleeve Tee");
+        assert_eq!(strip_phrase("Acmeware mug", "acme"), "Acmeware mug");
+        assert_eq!(replace_words("Tee and tees, TEE", "tee", "T-shirt"), "T-shirt and tees, T-shirt");
+        assert_eq!(replace_words("iphone case for IPHONE 15", "iphone", "iPhone"), "iPhone case for iPhone 15");
+    }
+}
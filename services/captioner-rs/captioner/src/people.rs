@@ -0,0 +1,226 @@
+// People in lifestyle shots (`people` in the alt text policy). refine_alt leaves them out of the
+// product phrase; with `neutral` or `as_captioned` they come back as a clause after it, so the
+// product stays the subject: "Red dress worn by a person". `neutral` says "person" or "people"
+// (a model stays a model) and "their"; `as_captioned` keeps the caption's noun. Either way,
+// words about age, body or ethnicity are dropped rather than repeated from a guess.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::alt_policy::People;
+use crate::taxonomy::Taxonomy;
+
+// (one, several)
+const NOUNS: &[(&str, &str)] = &[
+    ("woman", "women"),
+    ("man", "men"),
+    ("person", "people"),
+    ("girl", "girls"),
+    ("boy", "boys"),
+    ("lady", "ladies"),
+    ("gentleman", "gentlemen"),
+    ("guy", "guys"),
+    ("model", "models"),
+];
+
+// Said of the person, not the product; never kept.
+const DESCRIPTORS: &[&str] = &[
+    "young", "younger", "old", "older", "elderly", "middle", "aged", "teenage", "adult", "little", "tall", "short", "fat", "thin", "slim",
+    "skinny", "curvy", "petite", "muscular", "overweight", "pregnant", "pretty", "beautiful", "handsome", "attractive", "cute",
+    "asian", "african", "american", "caucasian", "black", "white", "brown", "latina", "latino", "hispanic", "indian", "blonde",
+    "blond", "brunette", "redhead", "bald", "bearded", "skinned", "haired", "dark", "light", "fair",
+];
+
+const ARTICLES: &[&str] = &["a", "an", "the", "one"];
+// Words between an article and the person noun it belongs to, at most
+const MAX_GAP: usize = 3;
+const BREAKS: &[&str] = &["and", "or", "with", "of", "in", "on", "by", "to"];
+const COUNTS: &[&str] = &["two", "three", "four", "five", "several", "some", "many"];
+
+// A verb after the person -> how the product relates to them
+const RELATIONS: &[(&str, &str)] = &[
+    ("wearing", "worn by"),
+    ("wears", "worn by"),
+    ("dressed", "worn by"),
+    ("holding", "held by"),
+    ("holds", "held by"),
+    ("carrying", "carried by"),
+    ("carries", "carried by"),
+    ("using", "used by"),
+    ("uses", "used by"),
+];
+const OTHERWISE: &str = "shown with";
+
+struct Mention {
+    // Word span, from the article or count to the noun
+    start: usize,
+    end: usize,
+    det: Option<String>,
+    noun: String,
+    plural: bool,
+}
+
+impl Mention {
+    fn noun(&self, mode: People) -> String {
+        match mode {
+            People::Neutral if !self.noun.starts_with("model") => if self.plural { "people" } else { "person" }.to_string(),
+            _ => self.noun.clone(),
+        }
+    }
+
+    fn say(&self, mode: People) -> String {
+        let noun = self.noun(mode);
+        match (self.det.as_deref(), self.plural) {
+            (Some("the"), _) => format!("the {noun}"),
+            (Some(d), true) if COUNTS.contains(&d) => format!("{d} {noun}"),
+            (_, true) => noun,
+            (_, false) => format!("a {noun}"),
+        }
+    }
+}
+
+fn mentions(words: &[&str]) -> Vec<Mention> {
+    let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    let mut found: Vec<Mention> = Vec::new();
+    for (i, w) in lower.iter().enumerate() {
+        let Some(&(one, several)) = NOUNS.iter().find(|(one, several)| w == one || w == several) else { continue };
+        // Whatever sits between the article and the noun describes the person ("a smiling man").
+        let is_det = |w: &str| ARTICLES.contains(&w) || COUNTS.contains(&w);
+        let det_at = (i.saturating_sub(MAX_GAP + 1)..i)
+            .rev()
+            .find(|&d| is_det(&lower[d]))
+            .filter(|&d| lower[d + 1..i].iter().all(|w| !is_det(w) && !BREAKS.contains(&w.as_str()) && !NOUNS.iter().any(|(o, s)| w == o || w == s)));
+        let mut start = det_at.unwrap_or(i);
+        while det_at.is_none() && start > 0 && DESCRIPTORS.contains(&lower[start - 1].as_str()) {
+            start -= 1;
+        }
+        let det = det_at.map(|d| lower[d].as_str());
+        let (noun, plural) = if w == several { (several, true) } else { (one, false) };
+        // "a woman model" is one person.
+        if let Some(prev) = found.last_mut().filter(|m| m.end == i && start == i) {
+            (prev.end, prev.noun, prev.plural) = (i + 1, noun.to_string(), plural);
+            continue;
+        }
+        found.push(Mention { start, end: i + 1, det: det.map(str::to_string), noun: noun.to_string(), plural });
+    }
+    found
+}
+
+// "a person and a person" -> "two people", when both are said the same way.
+fn pair_up(found: Vec<Mention>, lower: &[String], mode: People) -> Vec<Mention> {
+    let mut out: Vec<Mention> = Vec::new();
+    for m in found {
+        if let Some(prev) = out.last_mut()
+            && !prev.plural
+            && !m.plural
+            && m.start == prev.end + 1
+            && lower[prev.end] == "and"
+            && prev.noun(mode) == m.noun(mode)
+        {
+            let several = NOUNS.iter().find(|(one, _)| *one == m.noun).map_or("people", |(_, s)| s);
+            (prev.end, prev.det, prev.noun, prev.plural) = (m.end, Some("two".into()), several.to_string(), true);
+            continue;
+        }
+        out.push(m);
+    }
+    out
+}
+
+fn people_in(words: &[&str], mode: People) -> Vec<Mention> {
+    let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    pair_up(mentions(words), &lower, mode)
+}
+
+// How the product relates to the person whose mention ends before `after`: "in" only counts
+// when what follows is something worn ("a woman in a red dress", not "in a kitchen"); else the
+// first verb in RELATIONS after them.
+fn relation(lower: &[String], after: usize, tax: &Taxonomy) -> &'static str {
+    let rest = lower[after..].iter().skip_while(|w| ["is", "are"].contains(&w.as_str()));
+    let mut rest = rest.peekable();
+    if rest.peek().is_some_and(|w| *w == "in") {
+        let phrase: Vec<&str> = rest
+            .clone()
+            .skip(1)
+            .map(String::as_str)
+            .take_while(|w| !BREAKS.contains(w) && !RELATIONS.iter().any(|(v, _)| v == w))
+            .collect();
+        if tax.category_in_text(&phrase.join(" ")).is_some_and(|c| c.worn) {
+            return "worn by";
+        }
+    }
+    rest.find_map(|w| RELATIONS.iter().find(|(v, _)| v == w)).map_or(OTHERWISE, |(_, r)| r)
+}
+
+// "worn by a person" for the first person in `caption`, or None without one (or with `omit`).
+pub fn clause(caption: &str, mode: People, tax: &Taxonomy) -> Option<String> {
+    if mode == People::Omit {
+        return None;
+    }
+    let words: Vec<&str> = caption.unicode_words().collect();
+    let m = people_in(&words, mode).into_iter().next()?;
+    let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    Some(format!("{} {}", relation(&lower, m.end, tax), m.say(mode)))
+}
+
+// The caption with every person said the policy's way, for captions that name no product.
+pub fn rewrite(caption: &str, mode: People) -> String {
+    let words: Vec<&str> = caption.unicode_words().collect();
+    let found = people_in(&words, mode);
+    let mut out: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        if let Some(m) = found.iter().find(|m| m.start == i) {
+            out.push(m.say(mode));
+            i = m.end;
+            continue;
+        }
+        let w = words[i];
+        out.push(match w.to_lowercase().as_str() {
+            "her" | "his" if mode == People::Neutral => "their".to_string(),
+            _ => w.to_string(),
+        });
+        i += 1;
+    }
+    out.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clause(caption: &str, mode: People) -> Option<String> {
+        super::clause(caption, mode, &Taxonomy::builtin())
+    }
+
+    #[test]
+    fn neutral_and_as_captioned_clauses() {
+        let caption = "a young asian woman wearing a red dress";
+        assert_eq!(clause(caption, People::Neutral).as_deref(), Some("worn by a person"));
+        assert_eq!(clause(caption, People::AsCaptioned).as_deref(), Some("worn by a woman"));
+        assert_eq!(clause(caption, People::Omit), None);
+        assert_eq!(clause("two men holding coffee mugs", People::Neutral).as_deref(), Some("held by two people"));
+        assert_eq!(clause("a female model in a linen shirt", People::Neutral).as_deref(), Some("worn by a model"));
+        assert_eq!(clause("a woman model in a linen shirt", People::Neutral).as_deref(), Some("worn by a model"));
+        assert_eq!(clause("a man is standing next to a bike", People::AsCaptioned).as_deref(), Some("shown with a man"));
+        assert_eq!(clause("a red shoe on a white background", People::Neutral), None);
+        assert_eq!(clause("a man and a woman holding coffee mugs", People::Neutral).as_deref(), Some("held by two people"));
+    }
+
+    // "in" is only wearing when what follows is worn.
+    #[test]
+    fn in_means_worn_only_for_apparel() {
+        assert_eq!(clause("a woman in a kitchen holding a ceramic mug", People::Neutral).as_deref(), Some("held by a person"));
+        assert_eq!(clause("a man in a park next to a bike", People::Neutral).as_deref(), Some("shown with a person"));
+        assert_eq!(clause("a woman in black leather boots", People::Neutral).as_deref(), Some("worn by a person"));
+        assert_eq!(clause("a man is in a wool sweater", People::AsCaptioned).as_deref(), Some("worn by a man"));
+    }
+
+    #[test]
+    fn rewrites_every_mention_consistently() {
+        let caption = "an elderly man and a little girl sitting with his dog";
+        assert_eq!(rewrite(caption, People::Neutral), "two people sitting with their dog");
+        assert_eq!(rewrite(caption, People::AsCaptioned), "a man and a girl sitting with his dog");
+        assert_eq!(rewrite("the overweight guy on a bench", People::Neutral), "the person on a bench");
+        assert_eq!(rewrite("a man and a man on a bench", People::AsCaptioned), "two men on a bench");
+        assert_eq!(rewrite("a model and a woman on a bench", People::Neutral), "a model and a person on a bench");
+    }
+}
@@ -0,0 +1,1235 @@
+// Caption pipeline shared by /v1/caption and /v1/bulk. A request flows through named stages
+// (validate, fetch, decode, infer, clean, refine, rerank, people, existing_alt, localize,
+// speech, rules, policy, postprocess, candidates, long_description), each reading and
+// filling in the `CaptionCtx`. Stages can be replaced or disabled when the pipeline
+// is built; CAPTIONER_PIPELINE_DISABLE turns off optional stages by name.
+//
+// When inference fails, `infer` walks a fallback chain (CAPTIONER_FALLBACK, default
+// remote,local,tags,template) and records which level answered in `CaptionCtx::source`.
+// An image that can't be fetched or decoded skips the model levels when there is a product
+// title to caption from, and fails the request when there isn't.
+
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use bytes::Bytes;
+use image::DynamicImage;
+use serde::Serialize;
+use tokio::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::alt_policy::{self, AltTextPolicy};
+use crate::candidates::{self, Candidate};
+use crate::{describe, locale, people, speech};
+use crate::{AppState, CaptionReq, Result, engine, trace};
+use captioner::ApiError;
+#[cfg(not(feature = "turbo-ffi"))]
+use captioner::decode_image;
+#[cfg(feature = "turbo-ffi")]
+use captioner::decode;
+
+pub type StageFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+pub trait Stage: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a>;
+}
+
+// Everything a request accumulates on its way through the pipeline.
+pub struct CaptionCtx {
+    pub req: CaptionReq,
+    pub policy: AltTextPolicy,
+    pub image: Option<DynamicImage>,
+    pub output: Option<engine::EngineOutput>,
+    // Cleaned model caption, or the title template when the model gave none
+    pub raw: String,
+    pub alt: String,
+    // Per-tag scores from a remote endpoint, aligned with `output.tags`
+    pub tag_scores: Vec<f32>,
+    // Remote beam-search alternatives, best first
+    pub alternatives: Vec<crate::RemoteCaption>,
+    pub candidates: Vec<Candidate>,
+    // Text a remote endpoint read in the image
+    pub detected_text: Vec<String>,
+    pub long_description: Option<String>,
+    pub existing: Option<crate::existing::ExistingAlt>,
+    // Language `alt` ended up in, when one was requested or detected
+    pub locale: Option<String>,
+    // (requested locale, alt text) for `locales` after the first, and those with no wording
+    pub translations: Vec<(String, String)>,
+    pub untranslated_locales: Vec<String>,
+    pub refine: Option<crate::trace::RefineTrace>,
+    pub rerank: Option<Vec<trace::RerankScore>>,
+    // Fallback level that produced the caption, and whether it is below the best one available
+    pub source: Option<CaptionSource>,
+    pub degraded: bool,
+    // The image couldn't be fetched or decoded; the title stands in for it
+    pub image_unavailable: bool,
+    pub timings: Vec<(&'static str, Duration)>,
+}
+
+impl CaptionCtx {
+    pub fn new(req: CaptionReq, policy: AltTextPolicy) -> Self {
+        CaptionCtx {
+            req,
+            policy,
+            image: None,
+            output: None,
+            raw: String::new(),
+            alt: String::new(),
+            tag_scores: Vec::new(),
+            alternatives: Vec::new(),
+            candidates: Vec::new(),
+            detected_text: Vec::new(),
+            long_description: None,
+            existing: None,
+            locale: None,
+            translations: Vec::new(),
+            untranslated_locales: Vec::new(),
+            refine: None,
+            rerank: None,
+            source: None,
+            degraded: false,
+            image_unavailable: false,
+            timings: Vec::new(),
+        }
+    }
+
+    pub fn into_resp(self) -> crate::CaptionResp {
+        let debug = self.req.debug.then(|| self.debug_trace());
+        let translations = (self.req.locales.len() > 1).then(|| {
+            let digest = self.req.translatable_content_digest.clone().unwrap_or_else(|| locale::content_digest(&self.alt));
+            locale::TranslationsRegister::alt(self.req.resource_id.clone(), digest, self.translations)
+        });
+        crate::CaptionResp {
+            alt_text: self.alt,
+            tags: self.output.map(|o| o.tags).unwrap_or_default(),
+            degraded: self.degraded,
+            source: self.source,
+            candidates: self.candidates,
+            long_description: self.long_description,
+            existing_alt: self.existing,
+            locale: self.locale,
+            translations,
+            untranslated_locales: self.untranslated_locales,
+            debug,
+        }
+    }
+
+    fn debug_trace(&self) -> trace::DebugTrace {
+        let tags = self
+            .tags()
+            .iter()
+            .enumerate()
+            .map(|(i, t)| trace::TagScore { tag: t.clone(), score: self.tag_scores.get(i).copied() })
+            .collect();
+        trace::DebugTrace {
+            model_caption: self.output.as_ref().map(|o| o.caption.clone()).unwrap_or_default(),
+            cleaned_caption: self.raw.clone(),
+            tags,
+            refine: self.refine.clone(),
+            rerank: self.rerank.clone(),
+            stages: self.timings.iter().map(|(stage, d)| trace::StageTiming { stage, ms: d.as_secs_f64() * 1000.0 }).collect(),
+        }
+    }
+
+    pub fn tags(&self) -> &[String] {
+        self.output.as_ref().map_or(&[], |o| &o.tags)
+    }
+
+    // Image bytes, fetched on first use and kept on the request (remote inference forwards them).
+    async fn bytes(&mut self, state: &AppState) -> Result<Bytes> {
+        if let Some(b) = &self.req.image_bytes {
+            return Ok(b.clone());
+        }
+        let b = state.fetcher.fetch_bytes(&self.req.image_url).await?;
+        self.req.image_bytes = Some(b.clone());
+        Ok(b)
+    }
+
+    async fn decoded(&mut self, state: &AppState) -> Result<DynamicImage> {
+        if let Some(img) = &self.image {
+            return Ok(img.clone());
+        }
+        let bytes = self.bytes(state).await?;
+        let img = {
+            #[cfg(feature = "turbo-ffi")]
+            {
+                let _permit = state.decode_limit.clone().acquire_owned().await.unwrap();
+                decode(&bytes).await?
+            }
+            #[cfg(not(feature = "turbo-ffi"))]
+            {
+                decode_image(bytes).await?
+            }
+        };
+        self.image = Some(img.clone());
+        Ok(img)
+    }
+
+    // Keep going without the image when a product title can caption it, else fail with `e`.
+    fn image_failed(&mut self, e: ApiError) -> Result<()> {
+        if self.req.product_title.as_deref().is_none_or(|t| t.trim().is_empty()) {
+            return Err(e);
+        }
+        tracing::warn!(err = %e, "image unavailable; captioning from the title");
+        self.image_unavailable = true;
+        Ok(())
+    }
+}
+
+pub struct CaptionPipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+// Stages every caption needs; the rest may be disabled.
+const REQUIRED: &[&str] = &["validate", "fetch", "decode", "infer", "policy"];
+
+impl CaptionPipeline {
+    pub fn standard() -> Self {
+        CaptionPipeline {
+            stages: vec![
+                Box::new(Validate),
+                Box::new(Fetch),
+                Box::new(Decode),
+                Box::new(Infer::default()),
+                Box::new(Clean),
+                Box::new(Refine),
+                Box::new(Rerank::default()),
+                Box::new(People),
+                Box::new(ExistingAlt),
+                Box::new(Localize),
+                Box::new(Speech),
+                Box::new(Rules),
+                Box::new(Policy),
+                Box::new(Postprocess),
+                Box::new(Candidates),
+                Box::new(LongDescription::default()),
+            ],
+        }
+    }
+
+    pub fn from_env() -> std::result::Result<Self, String> {
+        let mut p = Self::standard();
+        if let Ok(chain) = std::env::var("CAPTIONER_FALLBACK")
+            && !chain.trim().is_empty()
+        {
+            p = p.replace(Box::new(Infer::new(CaptionSource::parse_chain(&chain)?)))?;
+        }
+        if let Ok(margin) = std::env::var("CAPTIONER_RERANK_MARGIN")
+            && !margin.trim().is_empty()
+        {
+            let margin = margin.trim().parse().map_err(|_| format!("CAPTIONER_RERANK_MARGIN: not a number: {margin:?}"))?;
+            p = p.replace(Box::new(Rerank { margin }))?;
+        }
+        if let Ok(max_len) = std::env::var("CAPTIONER_LONG_DESC_MAX_LEN")
+            && !max_len.trim().is_empty()
+        {
+            let max_len = max_len.trim().parse().map_err(|_| format!("CAPTIONER_LONG_DESC_MAX_LEN: not a number: {max_len:?}"))?;
+            p = p.replace(Box::new(LongDescription { max_len }))?;
+        }
+        for name in std::env::var("CAPTIONER_PIPELINE_DISABLE").unwrap_or_default().split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            p = p.without(name)?;
+        }
+        Ok(p)
+    }
+
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    fn position(&self, name: &str) -> std::result::Result<usize, String> {
+        self.stages.iter().position(|s| s.name() == name).ok_or_else(|| format!("unknown pipeline stage {name:?}"))
+    }
+
+    // For stages tests bring along; production stages are listed in `standard`.
+    #[cfg(test)]
+    pub fn insert_after(mut self, after: &str, stage: Box<dyn Stage>) -> std::result::Result<Self, String> {
+        let i = self.position(after)?;
+        self.stages.insert(i + 1, stage);
+        Ok(self)
+    }
+
+    pub fn replace(mut self, stage: Box<dyn Stage>) -> std::result::Result<Self, String> {
+        let i = self.position(stage.name())?;
+        self.stages[i] = stage;
+        Ok(self)
+    }
+
+    pub fn without(mut self, name: &str) -> std::result::Result<Self, String> {
+        if REQUIRED.contains(&name) {
+            return Err(format!("pipeline stage {name:?} cannot be disabled"));
+        }
+        let i = self.position(name)?;
+        self.stages.remove(i);
+        Ok(self)
+    }
+
+    pub async fn run(&self, state: &AppState, req: CaptionReq) -> Result<CaptionCtx> {
+        let mut policy = state.alt_policies.resolve(req.shop.as_deref(), None);
+        if let Some(rules) = req.shop.as_deref().and_then(|s| state.rules.get(s)) {
+            policy = rules.policy(policy);
+        }
+        if let Some(o) = &req.alt_policy {
+            policy = policy.with(o);
+        }
+        let mut ctx = CaptionCtx::new(req, policy);
+        for stage in &self.stages {
+            let t0 = Instant::now();
+            stage.run(state, &mut ctx).await?;
+            ctx.timings.push((stage.name(), t0.elapsed()));
+        }
+        tracing::debug!(timings = ?ctx.timings, "caption pipeline");
+        if ctx.degraded {
+            tracing::warn!(source = ?ctx.source, "served degraded caption");
+        }
+        Ok(ctx)
+    }
+}
+
+// Resolve `data:` URIs (bulk items; the upload extractor already did it for single requests)
+// and require an image.
+struct Validate;
+
+impl Stage for Validate {
+    fn name(&self) -> &'static str { "validate" }
+    fn run<'a>(&'a self, _state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            crate::upload::resolve_data_uri(&mut ctx.req)?;
+            crate::validate_image(&ctx.req)?;
+            let locales = &ctx.req.locales;
+            if !locales.is_empty() && ctx.req.locale.is_some() {
+                return Err(ApiError::BadRequest(Cow::Borrowed("send either locale or locales")));
+            }
+            if locales.len() > locale::MAX_LOCALES {
+                return Err(ApiError::BadRequest(Cow::Owned(format!("too many locales (max {})", locale::MAX_LOCALES))));
+            }
+            if locales.iter().enumerate().any(|(i, l)| l.trim().is_empty() || locales[..i].iter().any(|m| m.eq_ignore_ascii_case(l))) {
+                return Err(ApiError::BadRequest(Cow::Borrowed("locales must be distinct and non-empty")));
+            }
+            Ok(())
+        })
+    }
+}
+
+// Every image is read here, through the fetcher's URL policy, so remote endpoints are sent
+// bytes and never a caller's URL to fetch from inside the network.
+struct Fetch;
+
+impl Stage for Fetch {
+    fn name(&self) -> &'static str { "fetch" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            if let Err(e) = ctx.bytes(state).await {
+                ctx.image_failed(e)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// Decoding is deferred to `infer` when a remote endpoint may answer instead.
+struct Decode;
+
+impl Stage for Decode {
+    fn name(&self) -> &'static str { "decode" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            if state.remote_infer_urls.is_empty()
+                && !ctx.image_unavailable
+                && let Err(e) = ctx.decoded(state).await
+            {
+                ctx.image_failed(e)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// Where a caption came from, best first. `tags` composes from the title and shop-supplied
+// product context alone; `template` is "<title> on a plain background".
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionSource {
+    Remote,
+    Local,
+    Tags,
+    Template,
+}
+
+impl CaptionSource {
+    const ALL: [CaptionSource; 4] = [CaptionSource::Remote, CaptionSource::Local, CaptionSource::Tags, CaptionSource::Template];
+
+    fn name(self) -> &'static str {
+        match self {
+            CaptionSource::Remote => "remote",
+            CaptionSource::Local => "local",
+            CaptionSource::Tags => "tags",
+            CaptionSource::Template => "template",
+        }
+    }
+
+    pub fn parse_chain(s: &str) -> std::result::Result<Vec<Self>, String> {
+        let chain = s
+            .split(',')
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+            .map(|n| Self::ALL.into_iter().find(|l| l.name() == n).ok_or_else(|| format!("unknown fallback level {n:?}")))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if chain.is_empty() {
+            return Err("fallback chain is empty".into());
+        }
+        Ok(chain)
+    }
+}
+
+// Walk the fallback chain: remote endpoints (starting point hashed from the image URL, uploads
+// round-robin, endpoints in backoff skipped), the local engine, then the model-free levels.
+pub struct Infer {
+    chain: Vec<CaptionSource>,
+}
+
+impl Default for Infer {
+    fn default() -> Self {
+        Infer { chain: CaptionSource::ALL.to_vec() }
+    }
+}
+
+impl Infer {
+    pub fn new(chain: Vec<CaptionSource>) -> Self {
+        Infer { chain }
+    }
+
+    async fn try_level(&self, level: CaptionSource, state: &AppState, ctx: &mut CaptionCtx) -> Result<bool> {
+        match level {
+            CaptionSource::Remote => {
+                if state.remote_infer_urls.is_empty() || ctx.image_unavailable {
+                    return Ok(false);
+                }
+                let urls = crate::filter_backoff(state, crate::rotate_urls(&state.remote_infer_urls, remote_start(state, &ctx.req)));
+                let bytes = ctx.bytes(state).await?;
+                match crate::remote_infer_failover_backoff(state, &urls, &ctx.req, &bytes).await {
+                    Ok(r) => {
+                        ctx.output = Some(r.output);
+                        ctx.tag_scores = r.tag_scores;
+                        ctx.alternatives = r.alternatives;
+                        ctx.detected_text = r.text;
+                        Ok(true)
+                    }
+                    Err(e) => {
+                        tracing::warn!(err = %e, "all remote inference endpoints failed");
+                        Ok(false)
+                    }
+                }
+            }
+            CaptionSource::Local => {
+                let Some(engine_tx) = &state.engine_tx else {
+                    return Ok(false);
+                };
+                if ctx.image_unavailable {
+                    return Ok(false);
+                }
+                // Decoded here when a remote endpoint might have answered instead.
+                let image = match ctx.decoded(state).await {
+                    Ok(image) => image,
+                    Err(e) => {
+                        ctx.image_failed(e)?;
+                        return Ok(false);
+                    }
+                };
+                let (tx, rx) = tokio::sync::oneshot::channel();
+                let job = engine::Job { image, tx };
+                if engine_tx.send(job).await.is_err() {
+                    tracing::warn!("local engine unavailable");
+                    return Ok(false);
+                }
+                match rx.await.map_err(|_| ApiError::Internal).and_then(|r| r) {
+                    Ok(o) => {
+                        ctx.output = Some(o);
+                        Ok(true)
+                    }
+                    Err(e) => {
+                        tracing::warn!(err = %e, "local inference failed");
+                        Ok(false)
+                    }
+                }
+            }
+            // Only answers when the title or product context names a category.
+            CaptionSource::Tags => {
+                let (_, tr) = crate::refine_alt_traced(&state.taxonomy, &ctx.req, "", &[]);
+                Ok(tr.category.is_some())
+            }
+            CaptionSource::Template => Ok(true),
+        }
+    }
+}
+
+impl Stage for Infer {
+    fn name(&self) -> &'static str { "infer" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let available = |l: &&CaptionSource| match l {
+                CaptionSource::Remote => !state.remote_infer_urls.is_empty(),
+                CaptionSource::Local => state.engine_tx.is_some(),
+                _ => true,
+            };
+            let best = self.chain.iter().find(available).copied();
+            for &level in &self.chain {
+                if self.try_level(level, state, ctx).await? {
+                    ctx.source = Some(level);
+                    ctx.degraded = Some(level) != best;
+                    return Ok(());
+                }
+            }
+            Err(ApiError::Internal)
+        })
+    }
+}
+
+fn remote_start(state: &AppState, req: &CaptionReq) -> usize {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    let n = state.remote_infer_urls.len().max(1);
+    if req.image_url.is_empty() {
+        return state.remote_rr.fetch_add(1, Ordering::Relaxed) % n;
+    }
+    let mut hasher = DefaultHasher::new();
+    req.image_url.hash(&mut hasher);
+    (hasher.finish() as usize) % n
+}
+
+// Strip caption boilerplate. Model-free levels get the tag composition or the title template.
+struct Clean;
+
+impl Stage for Clean {
+    fn name(&self) -> &'static str { "clean" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let caption = ctx.output.as_ref().map(|o| o.caption.clone()).unwrap_or_default();
+            ctx.raw = if ctx.source == Some(CaptionSource::Tags) {
+                crate::refine_alt_traced(&state.taxonomy, &ctx.req, "", &[]).0
+            } else if caption.is_empty() {
+                crate::make_caption(&ctx.req, &ctx.policy)?.alt_text
+            } else {
+                crate::clean_caption(state.locales.strip_prefix(&caption).to_string())
+            };
+            // Before refine, whose word splitting would leave "w/ 1/2" as "w 1 2"
+            if ctx.policy.speech {
+                ctx.raw = speech::normalize(&ctx.raw);
+            }
+            ctx.alt = ctx.raw.clone();
+            Ok(())
+        })
+    }
+}
+
+struct Refine;
+
+impl Stage for Refine {
+    fn name(&self) -> &'static str { "refine" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let (alt, refine) = crate::refine_alt_traced(&state.taxonomy, &ctx.req, &ctx.raw, ctx.tags());
+            ctx.alt = alt;
+            ctx.refine = Some(refine);
+            Ok(())
+        })
+    }
+}
+
+// Score the refined alt text, the compositions without each attribute it chose and a
+// person-free model caption against the image with CLIP, and keep the best-aligned one. Needs
+// the CLIP text encoder and a local image embedding; otherwise a no-op.
+struct Rerank {
+    margin: f32,
+}
+
+impl Default for Rerank {
+    fn default() -> Self {
+        Rerank { margin: 0.01 }
+    }
+}
+
+impl Stage for Rerank {
+    fn name(&self) -> &'static str { "rerank" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let Some(encoder) = state.text_encoder.clone() else {
+                return Ok(());
+            };
+            let Some(image) = ctx.output.as_ref().map(|o| o.embedding.clone()).filter(|e| !e.is_empty()) else {
+                return Ok(());
+            };
+            let mut texts: Vec<(String, String)> = vec![(ctx.alt.clone(), "refined".into())];
+            if let Some(tr) = &ctx.refine {
+                for choice in &tr.attributes {
+                    let omit = [choice.attribute.as_str()];
+                    let (alt, _) = crate::refine_alt_omitting(&state.taxonomy, &ctx.req, &ctx.raw, ctx.tags(), &omit);
+                    texts.push((alt, format!("without {}", choice.attribute)));
+                }
+            }
+            if !crate::contains_any(&ctx.raw, crate::PEOPLE) {
+                texts.push((ctx.raw.clone(), "model caption".into()));
+            }
+            let mut seen = std::collections::HashSet::new();
+            texts.retain(|(t, _)| !t.trim().is_empty() && seen.insert(t.to_lowercase()));
+
+            let batch: Vec<String> = texts.iter().map(|(t, _)| t.clone()).collect();
+            let embedded = tokio::task::spawn_blocking(move || encoder.embed(&batch)).await.map_err(|_| ApiError::Internal)?;
+            let embeddings = match embedded {
+                Ok(e) if e.iter().all(|v| v.len() == image.len()) => e,
+                Ok(_) => {
+                    tracing::warn!("CLIP text and image embeddings differ in size; skipping rerank");
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::warn!(err = %e, "CLIP text encoding failed; skipping rerank");
+                    return Ok(());
+                }
+            };
+            let scores: Vec<f32> = embeddings.iter().map(|e| crate::clip_text::similarity(&image, e)).collect();
+            let chosen = crate::clip_text::best_aligned(&scores, self.margin);
+            ctx.alt = texts[chosen].0.clone();
+            ctx.rerank = Some(
+                texts
+                    .into_iter()
+                    .zip(scores)
+                    .enumerate()
+                    .map(|(i, ((alt_text, reason), score))| trace::RerankScore { alt_text, reason, score, chosen: i == chosen })
+                    .collect(),
+            );
+            Ok(())
+        })
+    }
+}
+
+// People in the caption, described per the shop's policy after the product (see people.rs).
+struct People;
+
+impl Stage for People {
+    fn name(&self) -> &'static str { "people" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let mode = ctx.policy.people;
+            let Some(tr) = ctx.refine.as_ref().filter(|_| mode != alt_policy::People::Omit) else {
+                return Ok(());
+            };
+            match tr.branch {
+                trace::Branch::NoCategory if crate::contains_any(&ctx.raw, crate::PEOPLE) => {
+                    let alt = crate::clean_caption(people::rewrite(&ctx.raw, mode));
+                    ctx.alt = match ctx.req.vendor.as_deref() {
+                        Some(v) if !ctx.req.include_brand && !v.trim().is_empty() => crate::matching::strip_phrase(&alt, v),
+                        _ => alt,
+                    };
+                }
+                trace::Branch::Composed | trace::Branch::ShortOutputGuard => {
+                    if let Some(clause) = people::clause(&ctx.raw, mode, &state.taxonomy) {
+                        let alt = format!("{} {clause}", ctx.alt.trim_end_matches(['.', ' ']));
+                        if alt.graphemes(true).count() <= ctx.policy.max_len {
+                            ctx.alt = alt;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        })
+    }
+}
+
+// Keep, improve or replace the merchant's `existing_alt` (see existing.rs).
+struct ExistingAlt;
+
+impl Stage for ExistingAlt {
+    fn name(&self) -> &'static str { "existing_alt" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let Some(existing) = ctx.req.existing_alt.clone() else {
+                return Ok(());
+            };
+            let (alt, decision) = crate::existing::decide(&state.taxonomy, ctx, &existing);
+            ctx.alt = alt;
+            ctx.existing = Some(decision);
+            Ok(())
+        })
+    }
+}
+
+// Say the refined alt text in the requested (or the title's) language; see locale.rs. With
+// `locales`, the first is `alt` and the rest become `translations`, all from one inference.
+struct Localize;
+
+impl Stage for Localize {
+    fn name(&self) -> &'static str { "localize" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            // Kept or improved merchant text stays in the merchant's words.
+            let kept = ctx.existing.as_ref().is_some_and(|e| e.decision != crate::existing::Decision::Replace);
+            for l in ctx.req.locales.iter().skip(1) {
+                let text = if locale::Locales::is_english(l) {
+                    Some(ctx.alt.clone())
+                } else {
+                    state.locales.get(l).filter(|_| !kept).and_then(|(_, lang)| localize(state, ctx, lang))
+                };
+                match text {
+                    Some(t) => ctx.translations.push((l.clone(), t)),
+                    None => ctx.untranslated_locales.push(l.clone()),
+                }
+            }
+
+            let req = &ctx.req;
+            let requested = req.locales.first().or(req.locale.as_ref()).map(String::as_str).filter(|l| !l.trim().is_empty());
+            let target = match requested {
+                Some(l) => state.locales.get(l),
+                None => req.product_title.as_deref().and_then(|t| state.locales.detect(t)).and_then(|c| state.locales.get(c)),
+            };
+            let Some((code, lang)) = target else {
+                // English, or a language without vocabularies
+                ctx.locale = requested.map(|_| "en".to_string());
+                return Ok(());
+            };
+            if kept {
+                return Ok(());
+            }
+            match localize(state, ctx, lang) {
+                Some(alt) => {
+                    ctx.alt = alt;
+                    ctx.locale = Some(code.to_string());
+                }
+                None => {
+                    tracing::debug!(locale = code, "no localized wording; alt text stays in English");
+                    ctx.locale = Some("en".to_string());
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+// `ctx.alt` in `lang`, or None when the language has no words for the product.
+fn localize(state: &AppState, ctx: &CaptionCtx, lang: &locale::Lang) -> Option<String> {
+    let req = &ctx.req;
+    let brand = req.vendor.as_deref().filter(|v| req.include_brand && !v.trim().is_empty());
+    if ctx.source == Some(CaptionSource::Template) {
+        let title = if req.include_brand { req.product_title.clone() } else { req.title_without_vendor() };
+        return title.map(|t| lang.template(t.trim()));
+    }
+    let r = ctx.refine.as_ref().filter(|r| matches!(r.branch, trace::Branch::Composed | trace::Branch::ShortOutputGuard))?;
+    // Only what survived reranking
+    let shown = crate::matching::Tokens::new(&ctx.alt);
+    let values: Vec<locale::Value> = r
+        .attributes
+        .iter()
+        .filter_map(|a| {
+            let attr = state.taxonomy.attribute(&a.attribute)?;
+            shown.contains(attr.form_of(&a.value)).then_some(if attr.is_measure() {
+                locale::Value::Measure(&a.value)
+            } else {
+                locale::Value::Word(&a.value)
+            })
+        })
+        .collect();
+    lang.compose(r.category.as_deref()?, &values, brand)
+}
+
+// Screen-reader wording for English text when the shop's policy asks for it (`clean` has
+// already done the caption); runs before `policy` so the limit applies to what is read aloud.
+struct Speech;
+
+impl Stage for Speech {
+    fn name(&self) -> &'static str { "speech" }
+    fn run<'a>(&'a self, _state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            if !ctx.policy.speech {
+                return Ok(());
+            }
+            if ctx.locale.as_deref().is_none_or(locale::Locales::is_english) {
+                ctx.alt = speech::normalize(&ctx.alt);
+            }
+            for (l, t) in &mut ctx.translations {
+                if locale::Locales::is_english(l) {
+                    *t = speech::normalize(t);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+// The shop's rewrite rules (rules.rs), on text in the languages they cover, except merchant
+// text that was kept.
+struct Rules;
+
+impl Stage for Rules {
+    fn name(&self) -> &'static str { "rules" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let Some(rules) = ctx.req.shop.as_deref().and_then(|s| state.rules.get(s)) else {
+                return Ok(());
+            };
+            if ctx.existing.as_ref().is_some_and(|e| e.decision != crate::existing::Decision::Replace) {
+                return Ok(());
+            }
+            if rules.covers(ctx.locale.as_deref()) {
+                let (alt, applied) = rules.apply(&ctx.alt, &ctx.policy);
+                tracing::debug!(?applied, "rewrite rules");
+                ctx.alt = alt;
+            }
+            for (l, t) in &mut ctx.translations {
+                if rules.covers(Some(l)) {
+                    *t = rules.apply(t, &ctx.policy).0;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+struct Policy;
+
+impl Stage for Policy {
+    fn name(&self) -> &'static str { "policy" }
+    fn run<'a>(&'a self, _state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            ctx.alt = ctx.policy.apply(&ctx.alt);
+            for (_, t) in &mut ctx.translations {
+                *t = ctx.policy.apply(t);
+            }
+            Ok(())
+        })
+    }
+}
+
+// Collapse whitespace and sentence-case the result.
+struct Postprocess;
+
+impl Stage for Postprocess {
+    fn name(&self) -> &'static str { "postprocess" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let rules = ctx.req.shop.as_deref().and_then(|s| state.rules.get(s));
+            ctx.alt = postprocess(&ctx.alt, rules.as_deref());
+            for (_, t) in &mut ctx.translations {
+                *t = postprocess(t, rules.as_deref());
+            }
+            Ok(())
+        })
+    }
+}
+
+// Text opening with one of the shop's glossary spellings ("iPhone case") keeps its casing.
+pub fn postprocess(text: &str, rules: Option<&crate::rules::Rules>) -> String {
+    let joined = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if rules.is_some_and(|r| r.leads_with_glossary(&joined)) {
+        return joined;
+    }
+    crate::compose::sentence_case(&joined)
+}
+
+// Alternatives when the request asks for `candidates`: refine_alt compositions without each
+// attribute it chose, the cleaned model caption and remote beam-search alternatives, all
+// fitted to the same length policy.
+struct Candidates;
+
+impl Stage for Candidates {
+    fn name(&self) -> &'static str { "candidates" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            let n = ctx.req.candidates.unwrap_or(0);
+            if n == 0 {
+                return Ok(());
+            }
+            let mut all = vec![Candidate::new(ctx.alt.clone(), candidates::PRIMARY_SCORE, "primary")];
+            if let Some(tr) = &ctx.refine {
+                let total = tr.attributes.len();
+                for choice in &tr.attributes {
+                    let omit = [choice.attribute.as_str()];
+                    let (alt, _) = crate::refine_alt_omitting(&state.taxonomy, &ctx.req, &ctx.raw, ctx.tags(), &omit);
+                    let score = candidates::composition_score(total - 1, total);
+                    all.push(Candidate::new(alt, score, format!("without {}", choice.attribute)));
+                }
+            }
+            if let Some(o) = ctx.output.as_ref().filter(|o| !o.caption.is_empty()) {
+                all.push(Candidate::new(crate::clean_caption(o.caption.clone()), candidates::model_score(0, None), "model caption"));
+            }
+            for (i, alt) in ctx.alternatives.iter().enumerate() {
+                let score = candidates::model_score(i, alt.score);
+                all.push(Candidate::new(crate::clean_caption(alt.caption.clone()), score, format!("model alternative {}", i + 1)));
+            }
+            // The primary is the finished alt text; the others are English and go through the
+            // same stages it did.
+            let rules = ctx.req.shop.as_deref().and_then(|s| state.rules.get(s));
+            for c in all.iter_mut().skip(1) {
+                if ctx.policy.speech {
+                    c.alt_text = speech::normalize(&c.alt_text);
+                }
+                if let Some(r) = rules.as_ref().filter(|r| r.covers(None)) {
+                    c.alt_text = r.apply(&c.alt_text, &ctx.policy).0;
+                }
+                c.alt_text = postprocess(&ctx.policy.apply(&c.alt_text), rules.as_deref());
+            }
+            ctx.candidates = candidates::select(all, n);
+            Ok(())
+        })
+    }
+}
+
+// Sentences for complex images when the request asks for `long_description`.
+struct LongDescription {
+    max_len: usize,
+}
+
+impl Default for LongDescription {
+    fn default() -> Self {
+        LongDescription { max_len: describe::DEFAULT_MAX_LEN }
+    }
+}
+
+impl Stage for LongDescription {
+    fn name(&self) -> &'static str { "long_description" }
+    fn run<'a>(&'a self, state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+        Box::pin(async move {
+            if !ctx.req.long_description {
+                return Ok(());
+            }
+            let req = &ctx.req;
+            let brand = req.vendor.as_deref().filter(|_| req.include_brand);
+            let has_caption = ctx.output.as_ref().is_some_and(|o| !o.caption.is_empty());
+            let options = [("color", &req.variant.color), ("size", &req.variant.size), ("material", &req.variant.material)]
+                .into_iter()
+                .filter_map(|(k, v)| v.as_deref().map(|v| (k, v)))
+                .collect();
+            let facts = describe::Facts {
+                subject: describe::subject(&state.taxonomy, ctx.refine.as_ref(), brand, &ctx.alt),
+                caption: if has_caption && !crate::contains_any(&ctx.raw, crate::PEOPLE) { &ctx.raw } else { "" },
+                title: req.product_title.as_deref(),
+                vendor: req.vendor.as_deref(),
+                product_type: req.product_type.as_deref(),
+                options,
+                tags: ctx.tags(),
+                text: &ctx.detected_text,
+            };
+            ctx.long_description = Some(describe::compose(&facts, self.max_len));
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Shout;
+
+    impl Stage for Shout {
+        fn name(&self) -> &'static str { "shout" }
+        fn run<'a>(&'a self, _state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+            Box::pin(async move {
+                ctx.alt = ctx.alt.to_uppercase();
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn stages_can_be_added_replaced_and_disabled() {
+        let p = CaptionPipeline::standard().insert_after("refine", Box::new(Shout)).unwrap();
+        assert_eq!(p.stage_names(), ["validate", "fetch", "decode", "infer", "clean", "refine", "shout", "rerank", "people", "existing_alt", "localize", "speech", "rules", "policy", "postprocess", "candidates", "long_description"]);
+        let p = p.without("shout").unwrap().without("postprocess").unwrap();
+        assert!(!p.stage_names().contains(&"postprocess"));
+        assert!(CaptionPipeline::standard().without("infer").is_err());
+        assert!(CaptionPipeline::standard().insert_after("nope", Box::new(Shout)).is_err());
+        assert!(CaptionPipeline::standard().replace(Box::new(Shout)).is_err());
+    }
+
+    #[tokio::test]
+    async fn custom_stage_runs_in_order() {
+        let state = crate::tests::dummy_state_with(
+            CaptionPipeline::standard().insert_after("refine", Box::new(Shout)).unwrap().without("postprocess").unwrap(),
+        );
+        let req = CaptionReq { image_bytes: Some(Bytes::from_static(crate::tests::SAMPLE_JPG)), product_title: Some("Trail Shoe".into()), ..Default::default() };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        assert_eq!(ctx.alt, "RED SHOE");
+        let names: Vec<&str> = ctx.timings.iter().map(|(n, _)| *n).collect();
+        assert_eq!(names, ["validate", "fetch", "decode", "infer", "clean", "refine", "shout", "rerank", "people", "existing_alt", "localize", "speech", "rules", "policy", "candidates", "long_description"]);
+    }
+
+    // State whose local engine has gone away.
+    fn without_engine(pipeline: CaptionPipeline) -> std::sync::Arc<AppState> {
+        let mut state = crate::tests::dummy_state_with(pipeline);
+        let (tx, _) = tokio::sync::mpsc::channel(1);
+        std::sync::Arc::get_mut(&mut state).unwrap().engine_tx = Some(tx);
+        state
+    }
+
+    fn upload(title: &str) -> CaptionReq {
+        CaptionReq { image_bytes: Some(Bytes::from_static(crate::tests::SAMPLE_JPG)), product_title: Some(title.into()), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn inference_failure_walks_the_fallback_chain() {
+        let state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let ctx = state.pipeline.run(&state, upload("Trail Shoe")).await.unwrap();
+        assert_eq!((ctx.source, ctx.degraded), (Some(CaptionSource::Local), false));
+
+        let state = without_engine(CaptionPipeline::standard());
+        let ctx = state.pipeline.run(&state, upload("Red Trail Shoe")).await.unwrap();
+        assert_eq!((ctx.source, ctx.degraded), (Some(CaptionSource::Tags), true));
+        assert_eq!(ctx.alt, "Red shoe");
+
+        let ctx = state.pipeline.run(&state, upload("Mystery Gift")).await.unwrap();
+        assert_eq!((ctx.source, ctx.degraded), (Some(CaptionSource::Template), true));
+        assert_eq!(ctx.alt, "Mystery Gift on a plain background");
+
+        let chain = CaptionSource::parse_chain("remote, local").unwrap();
+        let state = without_engine(CaptionPipeline::standard().replace(Box::new(Infer::new(chain))).unwrap());
+        assert!(matches!(state.pipeline.run(&state, upload("Red Trail Shoe")).await, Err(ApiError::Internal)));
+        assert!(CaptionSource::parse_chain("local,magic").is_err());
+    }
+
+    #[tokio::test]
+    async fn unreadable_images_fall_back_to_the_title() {
+        let state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let garbage = |title: Option<&str>| CaptionReq {
+            image_bytes: Some(Bytes::from_static(b"not an image")),
+            product_title: title.map(Into::into),
+            ..Default::default()
+        };
+        let ctx = state.pipeline.run(&state, garbage(Some("Mystery Gift"))).await.unwrap();
+        assert_eq!((ctx.source, ctx.degraded, ctx.image_unavailable), (Some(CaptionSource::Template), true, true));
+        assert_eq!(ctx.alt, "Mystery Gift on a plain background");
+        assert!(ctx.output.is_none());
+        assert!(state.pipeline.run(&state, garbage(None)).await.is_err());
+
+        let req = CaptionReq { image_url: "http://127.0.0.1:9/shoe.jpg".into(), product_title: Some("Mystery Gift".into()), ..Default::default() };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        assert_eq!((ctx.source, ctx.degraded), (Some(CaptionSource::Template), true));
+    }
+
+    // A remote /v1/infer endpoint that records the bodies it was sent.
+    async fn serve_remote() -> (String, std::sync::Arc<std::sync::Mutex<Vec<serde_json::Value>>>) {
+        use axum::{Json, routing::post};
+        let bodies = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = bodies.clone();
+        let app = axum::Router::new().route(
+            "/v1/infer",
+            post(move |Json(body): Json<serde_json::Value>| {
+                let seen = seen.clone();
+                async move {
+                    seen.lock().unwrap().push(body);
+                    Json(serde_json::json!({"caption": "a red shoe", "tags": ["shoe"]}))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{addr}"), bodies)
+    }
+
+    #[tokio::test]
+    async fn remote_endpoints_get_bytes_not_blocked_urls() {
+        let (remote, bodies) = serve_remote().await;
+        let mut state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        std::sync::Arc::get_mut(&mut state).unwrap().remote_infer_urls = vec![remote.clone()];
+
+        // Refused URLs are never fetched or forwarded; the title stands in for the image.
+        for url in ["http://169.254.169.254/latest/meta-data/", "http://127.0.0.1:9/admin.jpg", &format!("{remote}/v1/infer")] {
+            let req = CaptionReq { image_url: url.into(), product_title: Some("Red Shoe".into()), ..Default::default() };
+            let ctx = state.pipeline.run(&state, req).await.unwrap();
+            assert_eq!((ctx.source, ctx.degraded, ctx.image_unavailable), (Some(CaptionSource::Tags), true, true), "{url}");
+            let req = CaptionReq { image_url: url.into(), ..Default::default() };
+            let e = state.pipeline.run(&state, req).await.err().unwrap();
+            assert!(matches!(&e, ApiError::BadRequest(m) if m == "image url blocked by policy"), "{url}: {e}");
+        }
+        assert!(bodies.lock().unwrap().is_empty());
+
+        let ctx = state.pipeline.run(&state, upload("Red Shoe")).await.unwrap();
+        assert_eq!(ctx.source, Some(CaptionSource::Remote));
+        let sent = bodies.lock().unwrap();
+        assert!(sent[0]["image_b64"].is_string() && sent[0].get("image_url").is_none());
+    }
+
+    #[tokio::test]
+    async fn remote_endpoints_get_the_cdn_rendition() {
+        use axum::extract::RawQuery;
+        use base64::Engine as _;
+        let app = axum::Router::new().route(
+            "/s/files/shoe.jpg",
+            axum::routing::get(|RawQuery(q): RawQuery| async move { if q.is_some_and(|q| q.contains("width=")) { "small" } else { "original" } }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let cdn = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (remote, bodies) = serve_remote().await;
+        let mut state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let s = std::sync::Arc::get_mut(&mut state).unwrap();
+        s.remote_infer_urls = vec![remote];
+        let rule = crate::cdn::CdnRule { host: "127.0.0.1".into(), path_prefix: Some("/s/files/".into()), width_param: "width".into(), height_param: "height".into() };
+        s.fetcher = crate::fetch::Fetcher::new(crate::url_policy::UrlPolicy { allow_loopback: true, ..Default::default() })
+            .unwrap()
+            .with_cdn(crate::cdn::CdnRewriter::shopify(224).with_rule(rule));
+
+        let req = CaptionReq { image_url: format!("{cdn}/s/files/shoe.jpg"), product_title: Some("Red Shoe".into()), ..Default::default() };
+        state.pipeline.run(&state, req).await.unwrap();
+        let sent = bodies.lock().unwrap();
+        let image = base64::engine::general_purpose::STANDARD.decode(sent[0]["image_b64"].as_str().unwrap()).unwrap();
+        assert_eq!(image, b"small");
+    }
+
+    #[tokio::test]
+    async fn without_a_local_model_templates_are_not_degraded() {
+        let mut state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        std::sync::Arc::get_mut(&mut state).unwrap().engine_tx = None;
+        assert_eq!(state.mode(), "template-only (no local model)");
+        let ctx = state.pipeline.run(&state, upload("Red Trail Shoe")).await.unwrap();
+        assert_eq!((ctx.source, ctx.degraded), (Some(CaptionSource::Tags), false));
+    }
+
+    #[tokio::test]
+    async fn candidates_offer_shorter_compositions() {
+        let state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let req = CaptionReq { candidates: Some(3), ..upload("Red Leather Shoe with a Buckle") };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        let got: Vec<(&str, &str)> = ctx.candidates.iter().map(|c| (c.alt_text.as_str(), c.reason.as_str())).collect();
+        assert_eq!(got, [
+            ("Red leather shoe with a buckle", "primary"),
+            ("Leather shoe with a buckle", "without color"),
+            ("Red shoe with a buckle", "without material"),
+        ]);
+        assert!(ctx.candidates[1].score < ctx.candidates[0].score);
+
+        let ctx = state.pipeline.run(&state, upload("Red Leather Shoe")).await.unwrap();
+        assert!(ctx.candidates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn shop_rules_rewrite_alt_text_and_candidates() {
+        let dir = std::env::temp_dir().join(format!("captioner-pipeline-rules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("acme.myshopify.com.toml"), "banned = [\"leather\"]\nsuffix = \"by ACME\"\nmax_len = 40\n").unwrap();
+        let mut state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        std::sync::Arc::get_mut(&mut state).unwrap().rules = crate::rules::RuleBook::open(dir.clone()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let req = CaptionReq { shop: Some("acme.myshopify.com".into()), candidates: Some(2), ..upload("Red Leather Shoe with a Buckle") };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        assert_eq!(ctx.policy.max_len, 40);
+        assert_eq!(ctx.alt, "Red shoe with a buckle by ACME");
+        assert!(ctx.candidates.iter().all(|c| c.alt_text.ends_with("by ACME")));
+
+        let ctx = state.pipeline.run(&state, upload("Red Leather Shoe with a Buckle")).await.unwrap();
+        assert_eq!(ctx.alt, "Red leather shoe with a buckle");
+
+        // English rules leave French alone; the English candidates still get them.
+        let req = CaptionReq { shop: Some("acme.myshopify.com".into()), locale: Some("fr".into()), candidates: Some(2), ..upload("Red Leather Shoe with a Buckle") };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        assert_eq!(ctx.alt, "Chaussure rouge en cuir avec boucle");
+        assert_eq!(ctx.candidates[0].alt_text, ctx.alt);
+        assert!(ctx.candidates.len() == 2 && ctx.candidates.iter().skip(1).all(|c| c.alt_text.ends_with("by ACME")));
+    }
+
+    #[tokio::test]
+    async fn long_description_ignores_the_alt_text_limit() {
+        let state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let policy = crate::alt_policy::PolicyOverride { max_len: Some(20), ..Default::default() };
+        let req = CaptionReq { long_description: true, alt_policy: Some(policy), ..upload("Red Leather Shoe with a Buckle") };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        let long = ctx.long_description.unwrap();
+        assert!(long.starts_with("A red leather shoe with a buckle. Product: Red Leather Shoe with a Buckle."), "{long}");
+        assert!(ctx.alt.chars().count() <= 22);
+
+        let ctx = state.pipeline.run(&state, upload("Red Leather Shoe")).await.unwrap();
+        assert!(ctx.long_description.is_none());
+    }
+
+    #[tokio::test]
+    async fn localizes_requested_or_detected_language() {
+        let state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let say = async |req: CaptionReq| {
+            let ctx = state.pipeline.run(&state, req).await.unwrap();
+            (ctx.alt, ctx.locale)
+        };
+        let fr = say(CaptionReq { locale: Some("fr-CA".into()), ..upload("Red Leather Shoe with a Buckle") }).await;
+        assert_eq!(fr, ("Chaussure rouge en cuir avec boucle".into(), Some("fr".into())));
+        let de = say(upload("Schuh aus Leder mit Schnalle")).await;
+        assert_eq!(de, ("Roter Schuh".into(), Some("de".into())));
+        let ja = say(CaptionReq { locale: Some("ja".into()), ..upload("Red Leather Shoe") }).await;
+        assert_eq!(ja, ("Red leather shoe".into(), Some("en".into())));
+        assert_eq!(say(upload("Red Leather Shoe")).await.1, None);
+
+        let state = without_engine(CaptionPipeline::standard());
+        let ctx = state.pipeline.run(&state, CaptionReq { locale: Some("es".into()), ..upload("Mystery Gift") }).await.unwrap();
+        assert_eq!(ctx.alt, "Mystery Gift sobre fondo liso");
+    }
+
+    #[tokio::test]
+    async fn one_inference_serves_several_locales() {
+        let state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let locales = ["en", "fr", "de-DE", "ja"].map(String::from).to_vec();
+        let req = CaptionReq { locales, resource_id: Some("gid://shopify/MediaImage/1".into()), ..upload("Red Leather Shoe with a Buckle") };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        assert_eq!(ctx.timings.iter().filter(|(n, _)| *n == "infer").count(), 1);
+        let resp = serde_json::to_value(ctx.into_resp()).unwrap();
+        let digest = locale::content_digest("Red leather shoe with a buckle");
+        assert_eq!(resp["alt_text"], "Red leather shoe with a buckle");
+        assert_eq!(resp["translations"], serde_json::json!({
+            "resourceId": "gid://shopify/MediaImage/1",
+            "translations": [
+                {"locale": "fr", "key": "alt", "value": "Chaussure rouge en cuir avec boucle", "translatableContentDigest": digest},
+                {"locale": "de-DE", "key": "alt", "value": "Roter Schuh aus Leder mit Schnalle", "translatableContentDigest": digest},
+            ],
+        }));
+        assert_eq!(resp["untranslated_locales"], serde_json::json!(["ja"]));
+
+        let both = CaptionReq { locale: Some("fr".into()), locales: vec!["en".into(), "fr".into()], ..upload("Red Shoe") };
+        assert!(matches!(state.pipeline.run(&state, both).await, Err(ApiError::BadRequest(_))));
+        let many = CaptionReq { locales: (0..=locale::MAX_LOCALES).map(|i| format!("x-{i}")).collect(), ..upload("Red Shoe") };
+        let e = state.pipeline.run(&state, many).await.err().unwrap();
+        assert_eq!(e.to_string(), format!("too many locales (max {})", locale::MAX_LOCALES));
+    }
+
+    #[tokio::test]
+    async fn speech_policy_spells_out_titles() {
+        let state = without_engine(CaptionPipeline::standard());
+        let title = "🔥 Mystery Gift Box w/ 1/2 lb Candy 🔥";
+        let policy = crate::alt_policy::PolicyOverride { speech: Some(true), ..Default::default() };
+        let ctx = state.pipeline.run(&state, CaptionReq { alt_policy: Some(policy), ..upload(title) }).await.unwrap();
+        assert_eq!(ctx.alt, "Mystery Gift Box with half pound Candy on a plain background");
+        let ctx = state.pipeline.run(&state, upload(title)).await.unwrap();
+        assert!(!ctx.alt.contains("half"), "{}", ctx.alt);
+    }
+
+    // A model caption from a lifestyle shot.
+    struct Lifestyle(&'static str);
+
+    impl Stage for Lifestyle {
+        fn name(&self) -> &'static str { "lifestyle" }
+        fn run<'a>(&'a self, _state: &'a AppState, ctx: &'a mut CaptionCtx) -> StageFuture<'a> {
+            Box::pin(async move {
+                let o = ctx.output.as_mut().unwrap();
+                (o.caption, o.tags) = (self.0.to_string(), Vec::new());
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn people_policy_keeps_the_product_as_subject() {
+        let run = async |caption: &'static str, title: &str, people: Option<alt_policy::People>| {
+            let state = crate::tests::dummy_state_with(CaptionPipeline::standard().insert_after("infer", Box::new(Lifestyle(caption))).unwrap());
+            let policy = alt_policy::PolicyOverride { people, ..Default::default() };
+            state.pipeline.run(&state, CaptionReq { alt_policy: Some(policy), ..upload(title) }).await.unwrap().alt
+        };
+        let caption = "a young woman wearing a red dress";
+        assert_eq!(run(caption, "Silk Dress", None).await, "Red silk dress");
+        assert_eq!(run(caption, "Silk Dress", Some(alt_policy::People::Neutral)).await, "Red silk dress worn by a person");
+        assert_eq!(run(caption, "Silk Dress", Some(alt_policy::People::AsCaptioned)).await, "Red silk dress worn by a woman");
+        let nothing_known = "a smiling man with his dog in a park";
+        assert_eq!(run(nothing_known, "Gift Card", Some(alt_policy::People::Neutral)).await, "A person with their dog in a park");
+    }
+}
@@ -0,0 +1,424 @@
+// Per-shop rewrite rules: one TOML file per shop in CAPTIONER_RULES_DIR, named after the shop
+// domain ("acme.myshopify.com.toml"). They apply to the alt text after refinement, in order:
+// find/replace, banned terms, brand spellings from the glossary, then a required prefix and
+// suffix, which the length limit (optionally overridden by `max_len`) leaves whole. Rules are
+// written for English text; a file that also fits other languages lists them in `locales`.
+//
+// Files are validated when they load; the server refuses to start on a bad one. They are
+// checked for changes every CAPTIONER_RULES_RELOAD_SECS (default 30), and a file that no longer
+// validates is logged and skipped, so the shop keeps its last good rules.
+// `POST /v1/rules/dry-run` shows what a rule set does to sample captions, through the same
+// rules, policy and postprocess stages as a caption request.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, atomic::Ordering};
+use std::time::SystemTime;
+
+use axum::{Json, extract::State};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::alt_policy::{AltTextPolicy, Limit, PolicyOverride};
+use crate::{AppState, matching};
+use captioner::ApiError;
+
+pub const DEFAULT_RELOAD_SECS: u64 = 30;
+
+// Shortest `max_len` a rules file may set, not counting its prefix and suffix.
+const MIN_LEN: usize = 20;
+
+const MAX_SAMPLES: usize = 100;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default)]
+    replace: Vec<Replace>,
+    #[serde(default)]
+    banned: Vec<String>,
+    // Brand and product-line spellings, enforced whatever case the text used ("iPhone", "ACME")
+    #[serde(default)]
+    glossary: Vec<String>,
+    prefix: Option<String>,
+    suffix: Option<String>,
+    max_len: Option<usize>,
+    // Languages the rules are written for ("en", "fr"); English when empty
+    #[serde(default)]
+    locales: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Replace {
+    find: String,
+    with: String,
+}
+
+fn words(s: &str) -> usize {
+    s.unicode_words().count()
+}
+
+fn len(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+impl Rules {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let rules: Rules = toml::from_str(src).map_err(|e| e.to_string())?;
+        for (i, r) in rules.replace.iter().enumerate() {
+            if words(&r.find) == 0 {
+                return Err(format!("replace[{i}]: `find` needs at least one word"));
+            }
+            if rules.replace[..i].iter().any(|p| matching::replace_words(&p.find, &r.find, "").trim().is_empty()) {
+                return Err(format!("replace[{i}]: {:?} is already replaced", r.find));
+            }
+        }
+        for (what, list) in [("banned", &rules.banned), ("glossary", &rules.glossary)] {
+            for (i, term) in list.iter().enumerate() {
+                if words(term) == 0 {
+                    return Err(format!("{what}[{i}]: needs at least one word"));
+                }
+                if list[..i].iter().any(|t| t.eq_ignore_ascii_case(term)) {
+                    return Err(format!("{what}[{i}]: {term:?} is listed twice"));
+                }
+            }
+        }
+        if let Some(i) = rules.locales.iter().position(|l| language(l).is_empty()) {
+            return Err(format!("locales[{i}] is blank"));
+        }
+        for (what, s) in [("prefix", &rules.prefix), ("suffix", &rules.suffix)] {
+            if s.as_deref().is_some_and(|s| s.trim().is_empty()) {
+                return Err(format!("{what} is blank"));
+            }
+        }
+        // A banned term the rules themselves would write can never go away.
+        let written: Vec<&str> = rules.replace.iter().map(|r| r.with.as_str()).chain(rules.glossary.iter().map(String::as_str)).chain(rules.prefix.as_deref()).chain(rules.suffix.as_deref()).collect();
+        if let Some(term) = rules.banned.iter().find(|b| written.iter().any(|w| matching::replace_words(w, b, "") != *w)) {
+            return Err(format!("banned term {term:?} is written by another rule"));
+        }
+        if let Some(n) = rules.max_len
+            && n < MIN_LEN + rules.affix_len()
+        {
+            return Err(format!("max_len {n} leaves under {MIN_LEN} characters besides the prefix and suffix"));
+        }
+        Ok(rules)
+    }
+
+    // The shop's policy with the rules' `max_len`; a request's own override still comes after.
+    pub fn policy(&self, shop: AltTextPolicy) -> AltTextPolicy {
+        shop.with(&PolicyOverride { max_len: self.max_len, ..Default::default() })
+    }
+
+    // Whether text in `locale` (English when unset) gets these rules.
+    pub fn covers(&self, locale: Option<&str>) -> bool {
+        let lang = locale.map_or("en".to_string(), language);
+        if self.locales.is_empty() {
+            return lang == "en";
+        }
+        self.locales.iter().any(|l| language(l) == lang)
+    }
+
+    // Whether `text` opens with a glossary spelling that sentence casing would undo ("iPhone").
+    pub fn leads_with_glossary(&self, text: &str) -> bool {
+        self.glossary.iter().any(|g| text.strip_prefix(g.as_str()).is_some_and(|rest| !rest.starts_with(char::is_alphanumeric)))
+    }
+
+    fn affix_len(&self) -> usize {
+        [&self.prefix, &self.suffix].iter().filter_map(|s| s.as_deref()).map(|s| len(s.trim()) + 1).sum()
+    }
+
+    // `text` rewritten, fitted to `policy` around the prefix and suffix, and the rules that changed it.
+    pub fn apply(&self, text: &str, policy: &AltTextPolicy) -> (String, Vec<String>) {
+        let mut applied = Vec::new();
+        let mut text = text.to_string();
+        for r in &self.replace {
+            step(&mut text, &mut applied, format!("replace {:?} with {:?}", r.find, r.with), |t| matching::replace_words(t, &r.find, r.with.trim()));
+        }
+        for term in &self.banned {
+            step(&mut text, &mut applied, format!("banned {term:?}"), |t| tidy(&matching::replace_words(t, term, "")));
+        }
+        for term in &self.glossary {
+            step(&mut text, &mut applied, format!("glossary {term:?}"), |t| matching::replace_words(t, term, term));
+        }
+
+        let lower = text.to_lowercase();
+        let prefix = self.prefix.as_deref().map(str::trim).filter(|p| !lower.starts_with(&p.to_lowercase()));
+        let suffix = self.suffix.as_deref().map(str::trim).filter(|s| !lower.ends_with(&s.to_lowercase()));
+        let affixes: usize = prefix.iter().chain(&suffix).map(|s| len(s) + 1).sum();
+        if affixes > 0 {
+            let what = [prefix.map(|_| "prefix"), suffix.map(|_| "suffix")].into_iter().flatten().collect::<Vec<_>>().join(" and ");
+            // The text between them gets a hard limit: a soft one's slack would push the suffix
+            // past `max_len`, and the policy stage would then cut into it.
+            step(&mut text, &mut applied, what, |t| {
+                let fit = AltTextPolicy { max_len: policy.max_len.saturating_sub(affixes).max(1), limit: Limit::Hard, slack: 0, ..*policy };
+                let body = fit.apply(t);
+                [prefix, Some(body.as_str()), suffix].into_iter().flatten().collect::<Vec<_>>().join(" ")
+            });
+        }
+        (text, applied)
+    }
+}
+
+// "fr-CA" -> "fr"
+fn language(locale: &str) -> String {
+    locale.trim().split(['-', '_']).next().unwrap_or("").to_ascii_lowercase()
+}
+
+fn step(text: &mut String, applied: &mut Vec<String>, what: String, rule: impl FnOnce(&str) -> String) {
+    let next = rule(text);
+    if next != *text {
+        *text = next;
+        applied.push(what);
+    }
+}
+
+// Spacing and punctuation left behind by a removed term.
+fn tidy(text: &str) -> String {
+    let mut out = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    for (from, to) in [(" ,", ","), (" .", "."), (",,", ","), ("( )", ""), ("()", "")] {
+        out = out.replace(from, to);
+    }
+    out.trim_matches(|c: char| c.is_whitespace() || matches!(c, ',' | '-' | '–' | '|' | ':')).to_string()
+}
+
+struct Loaded {
+    modified: Option<SystemTime>,
+    rules: Arc<Rules>,
+}
+
+#[derive(Default)]
+pub struct RuleBook {
+    dir: Option<PathBuf>,
+    shops: RwLock<HashMap<String, Loaded>>,
+}
+
+impl RuleBook {
+    // Every rules file must load at startup.
+    pub fn from_env() -> Result<Self, String> {
+        let Some(dir) = std::env::var("CAPTIONER_RULES_DIR").ok().filter(|d| !d.trim().is_empty()) else {
+            return Ok(RuleBook::default());
+        };
+        RuleBook::open(PathBuf::from(dir.trim()))
+    }
+
+    pub fn open(dir: PathBuf) -> Result<Self, String> {
+        let book = RuleBook { dir: Some(dir), shops: RwLock::default() };
+        let errors = book.reload()?;
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(book),
+        }
+    }
+
+    pub fn get(&self, shop: &str) -> Option<Arc<Rules>> {
+        self.shops.read().unwrap().get(&shop.trim().to_ascii_lowercase()).map(|l| l.rules.clone())
+    }
+
+    // Load new and changed files and forget deleted ones. Files that fail to load are returned
+    // as errors and their shops keep what they had.
+    pub fn reload(&self) -> Result<Vec<String>, String> {
+        let Some(dir) = &self.dir else { return Ok(Vec::new()) };
+        let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        let mut seen = Vec::new();
+        let mut errors = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(shop) = path.file_name().and_then(|n| n.to_str()).and_then(|n| n.strip_suffix(".toml")).map(str::to_ascii_lowercase) else {
+                continue;
+            };
+            let modified = entry.metadata().and_then(|m| m.modified()).ok();
+            seen.push(shop.clone());
+            if self.shops.read().unwrap().get(&shop).is_some_and(|l| l.modified == modified && modified.is_some()) {
+                continue;
+            }
+            match std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|src| Rules::parse(&src)) {
+                Ok(rules) => {
+                    info!(shop = %shop, "rewrite rules loaded");
+                    self.shops.write().unwrap().insert(shop, Loaded { modified, rules: Arc::new(rules) });
+                }
+                Err(e) => {
+                    // Reported once per edit, not on every check
+                    if let Some(l) = self.shops.write().unwrap().get_mut(&shop) {
+                        l.modified = modified;
+                    }
+                    errors.push(format!("{}: {e}", path.display()));
+                }
+            }
+        }
+        self.shops.write().unwrap().retain(|shop, _| seen.contains(shop));
+        Ok(errors)
+    }
+
+    // Poll the rules directory for changes.
+    pub fn watch(state: Arc<AppState>, every: std::time::Duration) {
+        if state.rules.dir.is_none() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(every);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let s = state.clone();
+                match tokio::task::spawn_blocking(move || s.rules.reload()).await {
+                    Ok(Ok(errors)) => errors.iter().for_each(|e| warn!(error = %e, "rewrite rules not reloaded")),
+                    Ok(Err(e)) => warn!(error = %e, "rewrite rules directory unreadable"),
+                    Err(e) => warn!(error = %e, "rewrite rules reload failed"),
+                }
+            }
+        });
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DryRunReq {
+    // A rule set to try, as a rules file's TOML; the shop's current rules when unset
+    rules: Option<String>,
+    // Shop whose length policy (and, without `rules`, rules) apply
+    shop: Option<String>,
+    samples: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct DryRunResp {
+    results: Vec<DryRun>,
+}
+
+#[derive(Serialize)]
+struct DryRun {
+    before: String,
+    after: String,
+    applied: Vec<String>,
+}
+
+pub async fn dry_run(State(state): State<Arc<AppState>>, Json(req): Json<DryRunReq>) -> Result<Json<DryRunResp>, ApiError> {
+    state.request_count.fetch_add(1, Ordering::Relaxed);
+    if req.samples.len() > MAX_SAMPLES {
+        return Err(ApiError::BadRequest(Cow::Borrowed("at most 100 samples")));
+    }
+    let rules = match (&req.rules, req.shop.as_deref()) {
+        (Some(src), _) => Arc::new(Rules::parse(src).map_err(|e| ApiError::BadRequest(Cow::Owned(format!("rules: {e}"))))?),
+        (None, Some(shop)) => state.rules.get(shop).ok_or(ApiError::BadRequest(Cow::Borrowed("no rules loaded for shop")))?,
+        (None, None) => return Err(ApiError::BadRequest(Cow::Borrowed("rules or shop required"))),
+    };
+    let policy = rules.policy(state.alt_policies.resolve(req.shop.as_deref(), None));
+    let results = req
+        .samples
+        .into_iter()
+        .map(|before| {
+            let (after, applied) = rules.apply(&before, &policy);
+            DryRun { after: crate::pipeline::postprocess(&policy.apply(&after), Some(&rules)), before, applied }
+        })
+        .collect();
+    Ok(Json(DryRunResp { results }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACME: &str = r#"
+banned = ["cheap", "best ever"]
+glossary = ["ACME", "TrailFlex"]
+suffix = "by ACME"
+max_len = 60
+
+[[replace]]
+find = "tee"
+with = "T-shirt"
+"#;
+
+    #[test]
+    fn applies_rules_in_order() {
+        let rules = Rules::parse(ACME).unwrap();
+        let policy = AltTextPolicy::new(60, Limit::Hard);
+        let (alt, applied) = rules.apply("Cheap red trailflex tee, best ever", &policy);
+        assert_eq!(alt, "red TrailFlex T-shirt by ACME");
+        assert_eq!(applied, ["replace \"tee\" with \"T-shirt\"", "banned \"cheap\"", "banned \"best ever\"", "glossary \"TrailFlex\"", "suffix"]);
+        assert_eq!(rules.apply("Blue mug by acme", &policy).0, "Blue mug by ACME");
+
+        // The suffix survives the length limit; the text before it is cut.
+        let (alt, _) = rules.apply("Red leather shoe with buckle and a very long description", &AltTextPolicy::new(40, Limit::Hard));
+        assert_eq!(alt, "Red leather shoe with buckle by ACME");
+    }
+
+    #[test]
+    fn keeps_the_suffix_whole_under_a_soft_limit() {
+        let rules = Rules::parse("suffix = \"by ACME Co\"").unwrap();
+        let policy = AltTextPolicy { slack: 4, ..AltTextPolicy::new(40, Limit::Soft) };
+        let (alt, _) = rules.apply("Red leather shoe with gold buckle", &policy);
+        assert_eq!(alt, "Red leather shoe with gold by ACME Co");
+        assert!(alt.graphemes(true).count() <= 40);
+        assert_eq!(policy.apply(&alt), alt);
+    }
+
+    #[tokio::test]
+    async fn dry_run_keeps_a_leading_glossary_term() {
+        let state = crate::tests::dummy_state_with(crate::pipeline::CaptionPipeline::standard());
+        let req = DryRunReq {
+            rules: Some("glossary = [\"iPhone\"]".into()),
+            shop: None,
+            samples: vec!["iphone 15 case, blue".into(), "blue  case for iphone".into()],
+        };
+        let Json(resp) = dry_run(State(state), Json(req)).await.unwrap();
+        let after: Vec<&str> = resp.results.iter().map(|r| r.after.as_str()).collect();
+        assert_eq!(after, ["iPhone 15 case, blue", "Blue case for iPhone"]);
+        assert!(!Rules::parse("glossary = [\"iPhone\"]").unwrap().leads_with_glossary("iPhoneX case"));
+    }
+
+    #[test]
+    fn covers_english_unless_locales_are_listed() {
+        let rules = Rules::parse(ACME).unwrap();
+        assert!(rules.covers(None) && rules.covers(Some("en-GB")));
+        assert!(!rules.covers(Some("fr")));
+        let rules = Rules::parse("locales = [\"en\", \"fr-CA\"]").unwrap();
+        assert!(rules.covers(Some("fr_FR")) && rules.covers(None));
+        assert!(!rules.covers(Some("de")));
+    }
+
+    #[test]
+    fn rejects_invalid_rule_sets() {
+        for (src, why) in [
+            ("banned = [\"\"]", "needs at least one word"),
+            ("glossary = [\"ACME\", \"acme\"]", "listed twice"),
+            ("prefix = \"  \"", "blank"),
+            ("locales = [\"en\", \" \"]", "locales[1] is blank"),
+            ("banned = [\"acme\"]\nsuffix = \"by ACME\"", "written by another rule"),
+            ("max_len = 10", "max_len 10"),
+            ("maxlen = 80", "unknown field"),
+            ("[[replace]]\nfind = \"tee\"\nwith = \"T-shirt\"\n[[replace]]\nfind = \"Tee\"\nwith = \"top\"", "already replaced"),
+        ] {
+            let e = Rules::parse(src).unwrap_err();
+            assert!(e.contains(why), "{src:?}: {e}");
+        }
+    }
+
+    #[test]
+    fn reloads_changed_files_and_keeps_the_last_good_rules() {
+        let dir = std::env::temp_dir().join(format!("captioner-rules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("acme.myshopify.com.toml");
+        std::fs::write(&file, "glossary = [\"ACME\"]").unwrap();
+        let book = RuleBook { dir: Some(dir.clone()), shops: RwLock::default() };
+        assert!(book.reload().unwrap().is_empty());
+        assert_eq!(book.get("ACME.myshopify.com").unwrap().glossary, ["ACME"]);
+
+        // A broken edit is reported and the old rules stay; a fixed one replaces them.
+        std::fs::write(&file, "glossary = [").unwrap();
+        book.shops.write().unwrap().values_mut().for_each(|l| l.modified = None);
+        assert_eq!(book.reload().unwrap().len(), 1);
+        assert_eq!(book.get("acme.myshopify.com").unwrap().glossary, ["ACME"]);
+        std::fs::write(&file, "glossary = [\"Acme Co\"]").unwrap();
+        book.shops.write().unwrap().values_mut().for_each(|l| l.modified = None);
+        book.reload().unwrap();
+        assert_eq!(book.get("acme.myshopify.com").unwrap().glossary, ["Acme Co"]);
+
+        std::fs::remove_file(&file).unwrap();
+        book.reload().unwrap();
+        assert!(book.get("acme.myshopify.com").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+// `POST /v1/seo`: an image title attribute, a product SEO title (≤70 characters) and a meta
+// description (≤160) from the same image and product context as /v1/caption. Attributes come
+// from the caption pipeline's refinement; no word may repeat more than a couple of times, so
+// merchant titles that pile up keywords ("Shoe Shoes Leather Shoe") don't get worse.
+
+use std::sync::{Arc, atomic::Ordering};
+
+use axum::{
+    Json,
+    extract::State,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::alt_policy::{AltTextPolicy, Limit};
+use crate::matching;
+use crate::pipeline::CaptionCtx;
+use crate::{AppState, describe, upload};
+
+pub const TITLE_MAX_LEN: usize = 70;
+pub const META_MAX_LEN: usize = 160;
+
+// Times a word may appear: once in titles, twice in the meta description.
+const TITLE_REPEATS: usize = 1;
+const META_REPEATS: usize = 2;
+
+// Words that don't count towards repetition and stay lowercase in title case.
+const SMALL: &[&str] = &["a", "an", "the", "and", "or", "with", "of", "for", "in", "on", "by", "to", "at"];
+
+#[derive(Serialize, Debug)]
+pub struct SeoResp {
+    image_title: String,
+    seo_title: String,
+    meta_description: String,
+    degraded: bool,
+}
+
+pub async fn seo(State(state): State<Arc<AppState>>, upload::CaptionInput(mut req): upload::CaptionInput) -> Response {
+    req.debug = false;
+    req.candidates = None;
+    state.request_count.fetch_add(1, Ordering::Relaxed);
+    match state.pipeline.run(&state, req).await {
+        Ok(ctx) => Json(build(&state, &ctx)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+fn build(state: &AppState, ctx: &CaptionCtx) -> SeoResp {
+    let req = &ctx.req;
+    let vendor = req.vendor.as_deref().map(str::trim).filter(|v| !v.is_empty());
+    let title = req.title_without_vendor().map(|t| limit_repeats(&t, TITLE_REPEATS));
+    let values: Vec<&str> = ctx.refine.iter().flat_map(|r| &r.attributes).map(|a| a.value.as_str()).collect();
+    let image_title = fit(&title_case(&ctx.alt), TITLE_MAX_LEN);
+
+    // "Oxford Shoe – Red Leather | ACME": attributes the title doesn't already name, then the vendor.
+    let seo_title = match &title {
+        Some(t) => {
+            let mut words = Budget::new(TITLE_REPEATS);
+            words.add(t);
+            let fresh: Vec<String> = values.iter().filter(|v| words.add(v)).map(|v| title_case(v)).collect();
+            let mut out = fit(t, TITLE_MAX_LEN);
+            let suffix = fresh.join(" ");
+            if !suffix.is_empty() && len(&out) + 3 + len(&suffix) <= TITLE_MAX_LEN {
+                out = format!("{out} – {suffix}");
+            }
+            if let Some(v) = vendor.filter(|v| words.add(v)) && len(&out) + 3 + len(v) <= TITLE_MAX_LEN {
+                out = format!("{out} | {v}");
+            }
+            out
+        }
+        None => image_title.clone(),
+    };
+
+    // "ACME Oxford Shoe: a red leather shoe with a buckle. Available in size 9."
+    let brand = vendor.filter(|_| req.include_brand);
+    let subject = describe::subject(&state.taxonomy, ctx.refine.as_ref(), brand, &ctx.alt);
+    let mut words = Budget::new(META_REPEATS);
+    let mut sentences: Vec<String> = Vec::new();
+    let lead = match (&title, vendor) {
+        (Some(t), Some(v)) => Some(format!("{v} {t}")),
+        (Some(t), None) => Some(t.clone()),
+        _ => None,
+    };
+    match lead {
+        Some(lead) if words.add(&lead) && words.add(&subject) => sentences.push(format!("{lead}: {}.", lowercase_article(&subject))),
+        _ => {
+            words.add(&subject);
+            sentences.push(format!("{subject}."));
+        }
+    }
+    let options: Vec<&str> = [&req.variant.color, &req.variant.size, &req.variant.material]
+        .into_iter()
+        .filter_map(|o| o.as_deref().map(str::trim).filter(|o| !o.is_empty()))
+        .collect();
+    if !options.is_empty() {
+        let available = format!("Available in {}.", options.join(", "));
+        if words.add(&available) {
+            sentences.push(available);
+        }
+    }
+    let meta_description = fit(&sentences.join(" "), META_MAX_LEN);
+
+    SeoResp { image_title, seo_title, meta_description, degraded: ctx.degraded }
+}
+
+// Counts content words across the parts of one output; a part that would push any word past
+// the limit is refused whole rather than trimmed into ungrammatical text.
+struct Budget {
+    max: usize,
+    seen: Vec<String>,
+}
+
+impl Budget {
+    fn new(max: usize) -> Self {
+        Budget { max, seen: Vec::new() }
+    }
+
+    fn add(&mut self, part: &str) -> bool {
+        let words = content_words(part);
+        let over = words.iter().any(|w| {
+            let n = self.seen.iter().filter(|s| *s == w).count() + words.iter().filter(|x| *x == w).count();
+            n > self.max
+        });
+        if !over {
+            self.seen.extend(words);
+        }
+        !over
+    }
+}
+
+pub fn content_words(text: &str) -> Vec<String> {
+    matching::tokenize(text).into_iter().filter(|w| !SMALL.contains(&w.as_str()) && !w.chars().all(|c| c.is_ascii_digit())).collect()
+}
+
+// Drop repeats of a word past `max` (plurals count as the same word), keeping first uses.
+pub fn limit_repeats(text: &str, max: usize) -> String {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let kept: Vec<&str> = text
+        .split_whitespace()
+        .filter(|w| {
+            let Some(key) = content_words(w).into_iter().next() else {
+                return true;
+            };
+            match counts.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, n)) => {
+                    *n += 1;
+                    *n <= max
+                }
+                None => {
+                    counts.push((key, 1));
+                    true
+                }
+            }
+        })
+        .collect();
+    kept.join(" ").trim_end_matches([',', ';', ':', '-', '–', '|']).trim_end().to_string()
+}
+
+// "red leather shoe with buckle" -> "Red Leather Shoe with Buckle"; words with capitals stay as written.
+fn title_case(text: &str) -> String {
+    text.split_whitespace()
+        .enumerate()
+        .map(|(i, w)| {
+            if i > 0 && (SMALL.contains(&w.to_lowercase().as_str()) || w.chars().any(char::is_uppercase)) {
+                w.to_string()
+            } else {
+                crate::compose::sentence_case(w)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// "A red shoe" -> "a red shoe" after a colon; other subjects are left as composed.
+fn lowercase_article(subject: &str) -> String {
+    for article in ["A ", "An "] {
+        if let Some(rest) = subject.strip_prefix(article) {
+            return format!("{}{rest}", article.to_lowercase());
+        }
+    }
+    subject.to_string()
+}
+
+fn fit(text: &str, max_len: usize) -> String {
+    AltTextPolicy::new(max_len, Limit::Hard).apply(text)
+}
+
+fn len(text: &str) -> usize {
+    unicode_segmentation::UnicodeSegmentation::graphemes(text, true).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CaptionReq, pipeline::CaptionPipeline};
+    use bytes::Bytes;
+
+    #[test]
+    fn limits_keyword_repetition() {
+        assert_eq!(limit_repeats("Shoe Shoes Leather Shoe Oxford Shoe", 1), "Shoe Leather Oxford");
+        assert_eq!(limit_repeats("Tee for Men and Women, Men's Tee", 1), "Tee for Men and Women");
+        let mut b = Budget::new(1);
+        assert!(b.add("Oxford Shoe"));
+        assert!(!b.add("Leather Shoe"));
+        assert!(b.add("Leather"));
+        assert_eq!(title_case("red leather shoe with buckle"), "Red Leather Shoe with Buckle");
+    }
+
+    #[tokio::test]
+    async fn titles_and_meta_description() {
+        let state = crate::tests::dummy_state_with(CaptionPipeline::standard());
+        let req = CaptionReq {
+            image_bytes: Some(Bytes::from_static(crate::tests::SAMPLE_JPG)),
+            product_title: Some("ACME Leather Shoe Shoes with Buckle".into()),
+            vendor: Some("ACME".into()),
+            ..Default::default()
+        };
+        let ctx = state.pipeline.run(&state, req).await.unwrap();
+        let r = build(&state, &ctx);
+        assert_eq!(r.image_title, "Red Leather Shoe with a Buckle");
+        assert_eq!(r.seo_title, "Leather Shoe with Buckle – Red | ACME");
+        assert_eq!(r.meta_description, "ACME Leather Shoe with Buckle: a red leather shoe with a buckle.");
+        assert!(r.seo_title.chars().count() <= TITLE_MAX_LEN && r.meta_description.chars().count() <= META_MAX_LEN);
+    }
+}
@@ -0,0 +1,328 @@
+// Non-HTTP image sources for bulk migrations: `file://` paths under configured roots and
+// `s3://bucket/key` objects on an S3-compatible store (AWS, MinIO, R2, ...).
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use captioner::ApiError;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+
+use crate::sigv4::{self, Credentials};
+
+#[derive(Default)]
+pub struct FileSource {
+    // Canonicalized; a path is served only if it canonicalizes to somewhere below one of these.
+    roots: Vec<PathBuf>,
+}
+
+impl FileSource {
+    pub fn new(roots: impl IntoIterator<Item = PathBuf>) -> Self {
+        let roots = roots
+            .into_iter()
+            .filter_map(|r| match r.canonicalize() {
+                Ok(c) => Some(c),
+                Err(e) => {
+                    tracing::warn!(root = %r.display(), err = %e, "ignoring missing file root");
+                    None
+                }
+            })
+            .collect();
+        FileSource { roots }
+    }
+
+    // CAPTIONER_FILE_ROOTS: comma-separated directories; file:// is disabled when unset.
+    pub fn from_env() -> Self {
+        let roots: Vec<PathBuf> = std::env::var("CAPTIONER_FILE_ROOTS")
+            .ok()
+            .map(|s| s.split(',').map(str::trim).filter(|p| !p.is_empty()).map(PathBuf::from).collect())
+            .unwrap_or_default();
+        Self::new(roots)
+    }
+
+    pub async fn fetch(&self, url: &Url) -> Result<Bytes, ApiError> {
+        if self.roots.is_empty() {
+            return Err(ApiError::BadRequest(Cow::Borrowed("file:// sources not enabled")));
+        }
+        let path = url
+            .to_file_path()
+            .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid file url")))?;
+        // Canonicalize first so `..` and symlinks cannot escape the roots.
+        let real = tokio::fs::canonicalize(&path)
+            .await
+            .map_err(|_| ApiError::BadRequest(Cow::Borrowed("image file not found")))?;
+        if !self.allows(&real) {
+            tracing::warn!(path = %path.display(), "file url outside configured roots");
+            return Err(ApiError::BadRequest(Cow::Borrowed("file path not allowed")));
+        }
+        tokio::fs::read(&real)
+            .await
+            .map(Bytes::from)
+            .map_err(|_| ApiError::BadRequest(Cow::Borrowed("read image file failed")))
+    }
+
+    fn allows(&self, real: &Path) -> bool {
+        self.roots.iter().any(|r| real.starts_with(r))
+    }
+}
+
+// Settings for s3:// sources. Read from the TOML file named by CAPTIONER_S3_CONFIG, then
+// overridden field by field from the environment.
+#[derive(Debug, Default, Deserialize)]
+pub struct S3Config {
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    // Path-style (`endpoint/bucket/key`) addressing; defaults to on for custom endpoints,
+    // which is what MinIO expects.
+    pub path_style: Option<bool>,
+    // Buckets s3:// URLs may name; every other bucket is refused, as is every bucket when empty.
+    #[serde(default)]
+    pub buckets: Vec<String>,
+}
+
+impl S3Config {
+    // `None` when nothing S3-related is configured, leaving s3:// disabled.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let mut cfg = match std::env::var("CAPTIONER_S3_CONFIG") {
+            Ok(path) => {
+                let raw = std::fs::read_to_string(&path).map_err(|e| format!("CAPTIONER_S3_CONFIG {path}: {e}"))?;
+                toml::from_str(&raw).map_err(|e| format!("CAPTIONER_S3_CONFIG {path}: {e}"))?
+            }
+            Err(_) => S3Config::default(),
+        };
+        let env = |k: &str| std::env::var(k).ok().filter(|v| !v.is_empty());
+        cfg.endpoint = env("CAPTIONER_S3_ENDPOINT").or(cfg.endpoint);
+        cfg.region = env("AWS_REGION").or_else(|| env("AWS_DEFAULT_REGION")).or(cfg.region);
+        cfg.access_key_id = env("AWS_ACCESS_KEY_ID").or(cfg.access_key_id);
+        cfg.secret_access_key = env("AWS_SECRET_ACCESS_KEY").or(cfg.secret_access_key);
+        cfg.session_token = env("AWS_SESSION_TOKEN").or(cfg.session_token);
+        if let Some(v) = env("CAPTIONER_S3_PATH_STYLE") {
+            cfg.path_style = Some(matches!(v.as_str(), "1" | "true"));
+        }
+        if let Some(v) = env("CAPTIONER_S3_BUCKETS") {
+            cfg.buckets = v.split(',').map(str::trim).filter(|b| !b.is_empty()).map(str::to_string).collect();
+        }
+        Ok((cfg.endpoint.is_some() || cfg.access_key_id.is_some()).then_some(cfg))
+    }
+}
+
+pub struct S3Source {
+    http: Client,
+    endpoint: Url,
+    region: String,
+    // Anonymous (unsigned) requests when absent, for public buckets.
+    creds: Option<Credentials>,
+    path_style: bool,
+    buckets: Vec<String>,
+}
+
+impl S3Source {
+    pub fn new(http: Client, cfg: S3Config) -> Result<Self, String> {
+        let region = cfg.region.unwrap_or_else(|| "us-east-1".into());
+        let custom = cfg.endpoint.is_some();
+        let endpoint = cfg.endpoint.unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+        let endpoint = Url::parse(&endpoint).map_err(|e| format!("s3 endpoint {endpoint}: {e}"))?;
+        let creds = match (cfg.access_key_id, cfg.secret_access_key) {
+            (Some(access_key), Some(secret_key)) => Some(Credentials { access_key, secret_key, session_token: cfg.session_token }),
+            (None, None) => None,
+            _ => return Err("s3 credentials need both access key id and secret access key".into()),
+        };
+        if cfg.buckets.is_empty() {
+            tracing::warn!("no CAPTIONER_S3_BUCKETS; every s3:// url will be refused");
+        }
+        let buckets = cfg.buckets.iter().map(|b| b.to_ascii_lowercase()).collect();
+        Ok(S3Source { http, endpoint, region, creds, path_style: cfg.path_style.unwrap_or(custom), buckets })
+    }
+
+    pub async fn fetch(&self, url: &Url) -> Result<Bytes, ApiError> {
+        let bucket = url.host_str().filter(|b| !b.is_empty());
+        let key = url.path().trim_start_matches('/');
+        let (Some(bucket), false) = (bucket, key.is_empty()) else {
+            return Err(ApiError::BadRequest(Cow::Borrowed("s3 url must be s3://bucket/key")));
+        };
+        if !self.buckets.iter().any(|b| b.eq_ignore_ascii_case(bucket)) {
+            tracing::warn!(bucket = %bucket, "s3 bucket not in CAPTIONER_S3_BUCKETS");
+            return Err(ApiError::BadRequest(Cow::Borrowed("s3 bucket not allowed")));
+        }
+        // `Url` keeps the key percent-encoded; decode so it is encoded exactly once for signing.
+        let key = percent_decode(key);
+        let (target, host, path) = self.object_location(bucket, &key)?;
+
+        let mut req = self.http.get(target);
+        if let Some(creds) = &self.creds {
+            let signed = sigv4::sign("GET", &host, &path, &[], creds, &self.region, SystemTime::now());
+            req = req.header("authorization", signed.authorization);
+            for (k, v) in signed.headers {
+                req = req.header(k, v);
+            }
+        }
+        let resp = req.send().await.map_err(|e| {
+            tracing::warn!(bucket = %bucket, err = %e, "s3 request failed");
+            ApiError::BadRequest(Cow::Borrowed("s3 object not fetchable"))
+        })?;
+        if !resp.status().is_success() {
+            tracing::warn!(bucket = %bucket, key = %key, status = %resp.status(), "s3 get object failed");
+            return Err(ApiError::BadRequest(Cow::Borrowed("s3 object not fetchable")));
+        }
+        resp.bytes()
+            .await
+            .map_err(|_| ApiError::BadRequest(Cow::Borrowed("read body failed")))
+    }
+
+    // (request url, host header, canonical path)
+    fn object_location(&self, bucket: &str, key: &str) -> Result<(Url, String, String), ApiError> {
+        let bad = || ApiError::BadRequest(Cow::Borrowed("invalid s3 bucket"));
+        let ep_host = self.endpoint.host_str().ok_or_else(bad)?;
+        let base = self.endpoint.path().trim_end_matches('/');
+        let (host, path) = if self.path_style {
+            (ep_host.to_string(), sigv4::encode_path(&format!("{base}/{bucket}/{key}")))
+        } else {
+            (format!("{bucket}.{ep_host}"), sigv4::encode_path(&format!("{base}/{key}")))
+        };
+        let host = match self.endpoint.port() {
+            Some(p) => format!("{host}:{p}"),
+            None => host,
+        };
+        let target = Url::parse(&format!("{}://{host}{path}", self.endpoint.scheme())).map_err(|_| bad())?;
+        Ok((target, host, path))
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && let (Some(h), Some(l)) = (hex(bytes[i + 1]), hex(bytes[i + 2]))
+        {
+            out.push(h << 4 | l);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, extract::Path as AxPath, http::HeaderMap, routing::get};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("captioner-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&d).unwrap();
+        d
+    }
+
+    #[tokio::test]
+    async fn file_source_serves_only_inside_roots() {
+        let root = temp_dir("root");
+        let outside = temp_dir("outside");
+        std::fs::write(root.join("a.jpg"), b"inside").unwrap();
+        std::fs::write(outside.join("b.jpg"), b"outside").unwrap();
+        let src = FileSource::new([root.clone()]);
+
+        let ok = src.fetch(&Url::from_file_path(root.join("a.jpg")).unwrap()).await.unwrap();
+        assert_eq!(&ok[..], b"inside");
+
+        let escape = Url::parse(&format!("file://{}/../{}/b.jpg", root.display(), outside.file_name().unwrap().to_string_lossy())).unwrap();
+        assert!(src.fetch(&escape).await.is_err());
+        assert!(src.fetch(&Url::from_file_path(outside.join("b.jpg")).unwrap()).await.is_err());
+        assert!(FileSource::default().fetch(&Url::from_file_path(root.join("a.jpg")).unwrap()).await.is_err());
+        std::fs::remove_dir_all(root).unwrap();
+        std::fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn file_source_rejects_symlink_escape() {
+        let root = temp_dir("symroot");
+        let outside = temp_dir("symoutside");
+        std::fs::write(outside.join("secret.jpg"), b"secret").unwrap();
+        let link = root.join("link.jpg");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(outside.join("secret.jpg"), &link).unwrap();
+        let src = FileSource::new([root.clone()]);
+        assert!(src.fetch(&Url::from_file_path(link).unwrap()).await.is_err());
+        std::fs::remove_dir_all(root).unwrap();
+        std::fs::remove_dir_all(outside).unwrap();
+    }
+
+    // Stand-in for an S3-compatible endpoint: echoes what it saw so addressing and signing
+    // can be checked without a real store.
+    async fn serve_s3() -> String {
+        let app = Router::new().route(
+            "/{bucket}/{*key}",
+            get(|AxPath((bucket, key)): AxPath<(String, String)>, headers: HeaderMap| async move {
+                let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+                let date = headers.contains_key("x-amz-date");
+                format!("{bucket}|{key}|{}|{date}", auth.starts_with("AWS4-HMAC-SHA256 Credential=minio/"))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn s3_source_signs_path_style_requests() {
+        let endpoint = serve_s3().await;
+        let cfg = S3Config {
+            endpoint: Some(endpoint),
+            access_key_id: Some("minio".into()),
+            secret_access_key: Some("minio-secret".into()),
+            buckets: vec!["catalog".into()],
+            ..Default::default()
+        };
+        let src = S3Source::new(Client::new(), cfg).unwrap();
+        let body = src.fetch(&Url::parse("s3://catalog/products/red%20shoe.jpg").unwrap()).await.unwrap();
+        assert_eq!(std::str::from_utf8(&body).unwrap(), "catalog|products/red shoe.jpg|true|true");
+    }
+
+    #[tokio::test]
+    async fn s3_source_refuses_other_buckets() {
+        let endpoint = serve_s3().await;
+        let cfg = S3Config { endpoint: Some(endpoint.clone()), buckets: vec!["catalog".into()], ..Default::default() };
+        let src = S3Source::new(Client::new(), cfg).unwrap();
+        for url in ["s3://secrets/keys.jpg", "s3://catalog-backup/a.jpg"] {
+            let e = src.fetch(&Url::parse(url).unwrap()).await.unwrap_err();
+            assert!(matches!(&e, ApiError::BadRequest(m) if m == "s3 bucket not allowed"), "{url}");
+        }
+        let none = S3Source::new(Client::new(), S3Config { endpoint: Some(endpoint), ..Default::default() }).unwrap();
+        assert!(none.fetch(&Url::parse("s3://catalog/a.jpg").unwrap()).await.is_err());
+    }
+
+    #[test]
+    fn s3_virtual_hosted_addressing_for_aws() {
+        let src = S3Source::new(Client::new(), S3Config { region: Some("eu-west-1".into()), ..Default::default() }).unwrap();
+        let (url, host, path) = src.object_location("bucket", "a b.jpg").unwrap();
+        assert_eq!(url.as_str(), "https://bucket.s3.eu-west-1.amazonaws.com/a%20b.jpg");
+        assert_eq!((host.as_str(), path.as_str()), ("bucket.s3.eu-west-1.amazonaws.com", "/a%20b.jpg"));
+        assert!(S3Source::new(Client::new(), S3Config { access_key_id: Some("x".into()), ..Default::default() }).is_err());
+    }
+
+    // Against a real store, e.g. `docker run -p 9000:9000 minio/minio server /data`:
+    // CAPTIONER_S3_ENDPOINT=http://127.0.0.1:9000 AWS_ACCESS_KEY_ID=minioadmin
+    // AWS_SECRET_ACCESS_KEY=minioadmin CAPTIONER_S3_BUCKETS=bucket CAPTIONER_TEST_S3_URL=s3://bucket/key.jpg
+    // cargo test minio -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn minio_get_object() {
+        let url = std::env::var("CAPTIONER_TEST_S3_URL").expect("CAPTIONER_TEST_S3_URL");
+        let cfg = S3Config::from_env().unwrap().expect("s3 configured");
+        let src = S3Source::new(Client::new(), cfg).unwrap();
+        let body = src.fetch(&Url::parse(&url).unwrap()).await.unwrap();
+        assert!(!body.is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+// Screen-reader wording for alt text (`speech = true` in a shop's alt text policy). Alt text is
+// read aloud, so fractions become words ("3/4 sleeve" -> "three-quarter sleeve"), symbols and
+// shorthand are spelled out ("&", "w/", "oz" after a number), and emoji and decorative
+// characters merchants put in titles are dropped. English only; localized text is left alone.
+
+// (fraction, on its own, after a whole number)
+const FRACTIONS: &[(&str, &str, &str)] = &[
+    ("1/2", "half", "and a half"),
+    ("1/4", "quarter", "and a quarter"),
+    ("3/4", "three-quarter", "and three-quarters"),
+    ("1/3", "one-third", "and a third"),
+    ("2/3", "two-thirds", "and two-thirds"),
+    ("1/8", "one-eighth", "and an eighth"),
+    ("3/8", "three-eighths", "and three-eighths"),
+    ("5/8", "five-eighths", "and five-eighths"),
+    ("7/8", "seven-eighths", "and seven-eighths"),
+];
+
+const VULGAR_FRACTIONS: &[(char, &str)] = &[
+    ('½', "1/2"),
+    ('¼', "1/4"),
+    ('¾', "3/4"),
+    ('⅓', "1/3"),
+    ('⅔', "2/3"),
+    ('⅛', "1/8"),
+    ('⅜', "3/8"),
+    ('⅝', "5/8"),
+    ('⅞', "7/8"),
+];
+
+// Units, only when they follow a number: (abbreviation, one, several). Single letters and
+// "in" also have to be written onto the number ("5in", not "size 5 in black").
+const UNITS: &[(&str, &str, &str)] = &[
+    ("oz", "ounce", "ounces"),
+    ("lb", "pound", "pounds"),
+    ("lbs", "pound", "pounds"),
+    ("mg", "milligram", "milligrams"),
+    ("g", "gram", "grams"),
+    ("kg", "kilogram", "kilograms"),
+    ("ml", "milliliter", "milliliters"),
+    ("cl", "centiliter", "centiliters"),
+    ("l", "liter", "liters"),
+    ("mm", "millimeter", "millimeters"),
+    ("cm", "centimeter", "centimeters"),
+    ("m", "meter", "meters"),
+    ("in", "inch", "inches"),
+    ("\"", "inch", "inches"),
+    ("″", "inch", "inches"),
+    ("ft", "foot", "feet"),
+    ("yd", "yard", "yards"),
+    ("pc", "piece", "pieces"),
+    ("pcs", "piece", "pieces"),
+    ("pk", "pack", "pack"),
+    ("ct", "count", "count"),
+    ("mah", "milliamp-hour", "milliamp-hours"),
+    ("w", "watt", "watts"),
+    ("v", "volt", "volts"),
+    ("%", "percent", "percent"),
+    ("°", "degree", "degrees"),
+    ("°f", "degree Fahrenheit", "degrees Fahrenheit"),
+    ("°c", "degree Celsius", "degrees Celsius"),
+];
+
+// Shorthand spelled out wherever it appears, matched case-insensitively.
+const SHORTHAND: &[(&str, &str)] = &[
+    ("&", "and"),
+    ("+", "and"),
+    ("@", "at"),
+    ("w/", "with"),
+    ("w/o", "without"),
+    ("approx.", "approximately"),
+    ("approx", "approximately"),
+    ("asst.", "assorted"),
+    ("incl.", "including"),
+    ("pkg", "package"),
+    ("qty", "quantity"),
+    ("vs.", "versus"),
+    ("vs", "versus"),
+    // A capital letter is read as its name ("tee shirt"), a lowercase one sometimes as a sound.
+    ("t-shirt", "T-shirt"),
+    ("t-shirts", "T-shirts"),
+    ("v-neck", "V-neck"),
+    ("u-neck", "U-neck"),
+];
+
+// Punctuation kept around a word; the word itself is looked up without it.
+const LEADING: &[char] = &['(', '"', '\'', '“', '‘'];
+const TRAILING: &[char] = &[',', '.', ';', ':', '!', '?', ')', '"', '\'', '”', '’'];
+
+pub fn normalize(text: &str) -> String {
+    let text = strip_decorations(text);
+    let words: Vec<Word> = text.split_whitespace().flat_map(Word::split).collect();
+    let mut out: Vec<String> = Vec::new();
+    // Whether the last word said was a number, and whether it was exactly one
+    let mut number: Option<bool> = None;
+    let mut i = 0;
+    while i < words.len() {
+        let w = &words[i];
+        let lower = w.core.to_lowercase();
+        let next = words.get(i + 1).map(|n| n.core.to_lowercase());
+        let mut said = None;
+        let mut consumed = 1;
+        if let Some(&(_, alone, after)) = FRACTIONS.iter().find(|(f, ..)| *f == lower) {
+            let whole = number.is_some() && w.lead.is_empty();
+            said = Some(if whole { after.to_string() } else { alone.to_string() });
+            number = Some(!whole);
+        } else if let Some(n) = number
+            && lower == "fl"
+            && next.as_deref() == Some("oz")
+        {
+            said = Some(if n { "fluid ounce" } else { "fluid ounces" }.to_string());
+            consumed = 2;
+            number = None;
+        } else if let Some(n) = number
+            && let Some(&(_, one, several)) = UNITS.iter().find(|(u, ..)| *u == lower)
+            && (w.glued || (lower.len() > 1 && lower != "in"))
+        {
+            said = Some(if n { one } else { several }.to_string());
+            number = None;
+        } else if lower == "x" && number.is_some() && next.as_deref().is_some_and(is_number) {
+            said = Some("by".to_string());
+            number = None;
+        } else if let Some(digits) = lower.strip_prefix('#').filter(|d| is_number(d)) {
+            said = Some(format!("number {digits}"));
+            number = Some(digits == "1");
+        } else if let Some(&(_, s)) = SHORTHAND.iter().find(|(k, _)| *k == lower || (w.trail.starts_with('.') && *k == format!("{lower}."))) {
+            said = Some(s.to_string());
+            number = None;
+        } else if let Some(rest) = lower.strip_prefix("w/").filter(|r| !r.is_empty() && r.chars().all(char::is_alphabetic)) {
+            // "w/logo"
+            said = Some(format!("with {}", &w.core[w.core.len() - rest.len()..]));
+            number = None;
+        } else if is_number(&lower) {
+            number = Some(lower == "1");
+        } else {
+            number = None;
+        }
+        let mut trail = words[i + consumed - 1].trail.clone();
+        let core = match said {
+            Some(s) => {
+                // "approx." said as "approximately" keeps no abbreviation dot.
+                if SHORTHAND.iter().any(|(k, _)| k.ends_with('.') && *k == format!("{lower}.")) {
+                    trail = trail.strip_prefix('.').unwrap_or(&trail).to_string();
+                }
+                s
+            }
+            None => slashes(&w.core),
+        };
+        // Glued pieces ("750ml") were split; they are separate words when said.
+        out.push(format!("{}{core}{trail}", w.lead));
+        i += consumed;
+    }
+    out.join(" ")
+}
+
+// A whitespace-separated word split into pieces that are said differently: "750ml," becomes
+// "750" and "ml,", "10x12" becomes "10", "x" and "12".
+struct Word {
+    lead: String,
+    core: String,
+    trail: String,
+    // Written onto the piece before it
+    glued: bool,
+}
+
+impl Word {
+    fn split(token: &str) -> Vec<Word> {
+        let body = token.trim_start_matches(LEADING);
+        let lead = &token[..token.len() - body.len()];
+        let core = body.trim_end_matches(TRAILING);
+        // 13" is inches, not a closing quote.
+        let core = match body[core.len()..].strip_prefix('"') {
+            Some(_) if core.ends_with(|c: char| c.is_ascii_digit()) => &body[..core.len() + 1],
+            _ => core,
+        };
+        let trail = &body[core.len()..];
+        let mut pieces: Vec<&str> = Vec::new();
+        let mut rest = core;
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',' || c == '/')).unwrap_or(rest.len());
+            let (piece, tail) = if digits > 0 && starts_number(rest) {
+                (&rest[..digits], &rest[digits..])
+            } else if pieces.last().is_some_and(|p| is_number(p)) {
+                // The unit after a number, up to the next number ("x" in "10x12")
+                let end = rest.find(|c: char| c.is_ascii_digit()).filter(|&e| e > 0 && rest[..e].eq_ignore_ascii_case("x")).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            } else {
+                (rest, "")
+            };
+            pieces.push(piece);
+            rest = tail;
+        }
+        if pieces.is_empty() {
+            pieces.push(core);
+        }
+        let n = pieces.len();
+        pieces
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| Word {
+                lead: if i == 0 { lead.to_string() } else { String::new() },
+                core: p.to_string(),
+                trail: if i == n - 1 { trail.to_string() } else { String::new() },
+                glued: i > 0,
+            })
+            .collect()
+    }
+}
+
+fn starts_number(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn is_number(s: &str) -> bool {
+    starts_number(s) && s.chars().all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+}
+
+// "black/white" -> "black and white"; slashes between numbers (fractions, dates) stay.
+fn slashes(word: &str) -> String {
+    let parts: Vec<&str> = word.split('/').collect();
+    if parts.len() > 1 && parts.iter().all(|p| !p.is_empty() && p.chars().all(char::is_alphabetic)) {
+        parts.join(" and ")
+    } else {
+        word.to_string()
+    }
+}
+
+// Emoji, pictographs, stars, arrows and trademark signs, which screen readers either skip or
+// read out by name; and ½-style fractions, written out so they are said as words.
+fn strip_decorations(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let Some((_, f)) = VULGAR_FRACTIONS.iter().find(|(v, _)| *v == c) {
+            out.push(' ');
+            out.push_str(f);
+        } else if is_decorative(c) {
+            out.push(' ');
+        } else if matches!(c, '|' | '•' | '·' | '¦') {
+            // Separators in titles ("Tee | Black | Cotton") read as a pause
+            out.push_str(" , ");
+        } else if c == '&' {
+            // "Salt&Pepper"
+            out.push_str(" & ");
+        } else {
+            out.push(c);
+        }
+    }
+    // Punctuation left stranded by what was removed ("Tee ★, black").
+    let words: Vec<&str> = out.split_whitespace().collect();
+    let mut tidy: Vec<String> = Vec::new();
+    for w in words {
+        if w.chars().all(|c| TRAILING.contains(&c) && c != ')') {
+            if let Some(last) = tidy.last_mut()
+                && !last.ends_with(TRAILING)
+            {
+                last.push_str(w);
+            }
+            continue;
+        }
+        tidy.push(w.to_string());
+    }
+    tidy.join(" ")
+}
+
+fn is_decorative(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF   // emoji, flags, pictographs
+        | 0x2600..=0x27BF   // misc symbols and dingbats (★ ✓ ✨ ❤)
+        | 0x2190..=0x21FF   // arrows
+        | 0x2500..=0x25FF   // box drawing, blocks, geometric shapes (● ■ ◆)
+        | 0x2B00..=0x2BFF   // more stars and arrows
+        | 0xFE00..=0xFE0F   // variation selectors
+        | 0xE0020..=0xE007F // emoji tag sequences
+        | 0x200D | 0x20E3   // joiner, keycap
+    ) || matches!(c, '™' | '®' | '©' | '*' | '~')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tests/fixtures/speech_corpus.tsv: before<TAB>after, one pair per line.
+    #[test]
+    fn corpus() {
+        let corpus = include_str!("../tests/fixtures/speech_corpus.tsv");
+        for line in corpus.lines().filter(|l| !l.trim().is_empty() && !l.starts_with('#')) {
+            let (before, after) = line.split_once('\t').expect("before<TAB>after");
+            assert_eq!(normalize(before), after, "{before:?}");
+        }
+    }
+}
@@ -0,0 +1,277 @@
+// Product taxonomy for alt text refinement: categories (with synonyms), attribute types and
+// which attribute types apply to each category. Loaded from taxonomy.toml at startup; the
+// file's header documents the format.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::compose::{Phrase, Slot};
+
+const BUILTIN: &str = include_str!("../taxonomy.toml");
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaxonomyFile {
+    #[serde(default)]
+    defaults: Defaults,
+    attributes: HashMap<String, Attribute>,
+    categories: Vec<CategoryDef>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Defaults {
+    #[serde(default)]
+    attributes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CategoryDef {
+    name: String,
+    #[serde(default)]
+    synonyms: Vec<String>,
+    attributes: Option<Vec<String>>,
+    #[serde(default)]
+    worn: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Attribute {
+    // None: attached after the noun ("shoe with buckle").
+    slot: Option<Slot>,
+    #[serde(default)]
+    values: Vec<String>,
+    #[serde(default)]
+    units: Vec<String>,
+    #[serde(default)]
+    forms: HashMap<String, String>,
+    #[serde(default)]
+    slots: HashMap<String, Slot>,
+    // Multi-word values that stay open compounds in front of the noun ("salted caramel")
+    #[serde(default)]
+    open: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Category {
+    pub name: String,
+    synonyms: Vec<String>,
+    // Clothing, footwear and accessories a person can be "in"
+    pub worn: bool,
+    // Indexes into `Taxonomy::attributes`, in the order they are added to the phrase.
+    attributes: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct Taxonomy {
+    categories: Vec<Category>,
+    names: Vec<String>,
+    attributes: Vec<Attribute>,
+}
+
+impl Taxonomy {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let file: TaxonomyFile = toml::from_str(src).map_err(|e| e.to_string())?;
+        let mut names: Vec<String> = file.attributes.keys().cloned().collect();
+        names.sort();
+        let mut by_name = file.attributes;
+        let attributes: Vec<Attribute> = names.iter().map(|n| by_name.remove(n).expect("key from map")).collect();
+
+        for (name, attr) in names.iter().zip(&attributes) {
+            if attr.values.is_empty() == attr.units.is_empty() {
+                return Err(format!("attribute {name:?} needs either values or units"));
+            }
+            if let Some(v) = attr.open.iter().find(|v| !attr.values.contains(v)) {
+                return Err(format!("attribute {name:?}: open value {v:?} is not in values"));
+            }
+        }
+        let index = |a: &String| {
+            names.iter().position(|n| n == a).ok_or_else(|| format!("unknown attribute {a:?}"))
+        };
+        let categories = file
+            .categories
+            .into_iter()
+            .map(|c| {
+                if c.name.trim().is_empty() {
+                    return Err("category without a name".to_string());
+                }
+                let attrs = c.attributes.as_ref().unwrap_or(&file.defaults.attributes);
+                Ok(Category {
+                    attributes: attrs.iter().map(index).collect::<Result<_, _>>()?,
+                    name: c.name,
+                    synonyms: c.synonyms,
+                    worn: c.worn,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if categories.is_empty() {
+            return Err("taxonomy defines no categories".into());
+        }
+        Ok(Taxonomy { categories, names, attributes })
+    }
+
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN).expect("embedded taxonomy.toml")
+    }
+
+    // CAPTIONER_TAXONOMY points at a replacement for the embedded taxonomy.
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("CAPTIONER_TAXONOMY") {
+            Ok(path) if !path.trim().is_empty() => {
+                let src = std::fs::read_to_string(path.trim()).map_err(|e| format!("{path}: {e}"))?;
+                Self::parse(&src).map_err(|e| format!("{path}: {e}"))
+            }
+            _ => Ok(Self::builtin()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn categories(&self) -> &[Category] {
+        &self.categories
+    }
+
+    pub fn category(&self, name: &str) -> Option<&Category> {
+        self.categories.iter().find(|c| c.name == name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.names.iter().position(|n| n == name).map(|i| &self.attributes[i])
+    }
+
+    pub fn attributes_of<'a>(&'a self, cat: &'a Category) -> impl Iterator<Item = (&'a str, &'a Attribute)> + 'a {
+        cat.attributes.iter().map(|&i| (self.names[i].as_str(), &self.attributes[i]))
+    }
+
+    // Category named in free text; the longest name wins ("phone case" over "phone"), then list order.
+    pub fn category_in_text(&self, text: &str) -> Option<&Category> {
+        let toks = crate::matching::Tokens::new(text);
+        let mut best: Option<(&Category, usize)> = None;
+        for cat in &self.categories {
+            let len = cat.names().flat_map(|n| toks.find_all(n)).map(|(s, e)| e - s).max();
+            if let Some(len) = len && best.is_none_or(|(_, l)| len > l) {
+                best = Some((cat, len));
+            }
+        }
+        best.map(|(c, _)| c)
+    }
+
+    // First category (in taxonomy order) that one of the tags names.
+    pub fn category_in_tags(&self, tags: &[String]) -> Option<&Category> {
+        self.categories
+            .iter()
+            .find(|c| c.names().any(|n| tags.iter().any(|t| crate::matching::same_phrase(t, n))))
+    }
+}
+
+impl Category {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.synonyms.iter().map(String::as_str))
+    }
+}
+
+impl Attribute {
+    pub fn values(&self) -> Vec<&str> {
+        self.values.iter().map(String::as_str).collect()
+    }
+
+    pub fn is_measure(&self) -> bool {
+        !self.units.is_empty()
+    }
+
+    // Attached with "with" unless the value has a slot of its own.
+    pub fn is_detail(&self) -> bool {
+        self.slot.is_none()
+    }
+
+    // A number followed by a unit, normalized to "<number> <unit>": "12oz" and "12 OZ" -> "12 oz".
+    pub fn measure_in(&self, text: &str) -> Option<String> {
+        let lower = text.to_lowercase();
+        let words: Vec<&str> = lower.split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '/' || c == '|' || c == '-').filter(|w| !w.is_empty()).collect();
+        let mut units: Vec<(&str, Vec<&str>)> = self.units.iter().map(|u| (u.as_str(), u.split_whitespace().collect())).collect();
+        // "fl oz" before "oz"
+        units.sort_by_key(|(_, parts)| std::cmp::Reverse(parts.len()));
+        for (i, w) in words.iter().enumerate() {
+            let w = w.trim_end_matches([',', '.', ';']);
+            let split = w.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',')).unwrap_or(w.len());
+            let (num, rest) = w.split_at(split);
+            if num.is_empty() || !num.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+            for (unit, parts) in &units {
+                let joined = !rest.is_empty() && rest == parts.concat();
+                let spaced = rest.is_empty()
+                    && parts.iter().enumerate().all(|(k, p)| {
+                        words.get(i + 1 + k).is_some_and(|x| x.trim_end_matches([',', '.', ';']) == *p)
+                    });
+                if joined || spaced {
+                    return Some(format!("{} {unit}", num.trim_end_matches(['.', ','])));
+                }
+            }
+        }
+        None
+    }
+
+    // Add a matched value to the phrase in its slot and surface form.
+    pub fn place(&self, phrase: Phrase, value: &str) -> Phrase {
+        match self.slot_of(value) {
+            Some(slot) if self.is_open(value) => phrase.open_modifier(slot, self.form_of(value)),
+            Some(slot) => phrase.modifier(slot, self.form_of(value)),
+            None => phrase.detail(self.form_of(value)),
+        }
+    }
+
+    pub fn slot_of(&self, value: &str) -> Option<Slot> {
+        self.slots.get(value).copied().or(self.slot)
+    }
+
+    pub fn is_open(&self, value: &str) -> bool {
+        self.open.iter().any(|v| v == value)
+    }
+
+    pub fn form_of<'a>(&'a self, value: &'a str) -> &'a str {
+        self.forms.get(value).map(String::as_str).unwrap_or(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_taxonomy_loads() {
+        let tax = Taxonomy::builtin();
+        let bottle = tax.category_in_text("Insulated Water Bottle").unwrap();
+        assert_eq!(bottle.name, "bottle");
+        let attrs: Vec<&str> = tax.attributes_of(bottle).map(|(n, _)| n).collect();
+        assert!(attrs.contains(&"capacity"));
+        assert_eq!(tax.category_in_text("Slim phone case").unwrap().name, "case");
+        assert_eq!(tax.category_in_tags(&["Trousers".into()]).unwrap().name, "pants");
+        assert!(tax.category_in_text("just some words").is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_attributes_and_fields() {
+        let src = "[attributes.color]\nvalues = [\"red\"]\n[[categories]]\nname = \"mug\"\nattributes = [\"colour\"]\n";
+        assert!(Taxonomy::parse(src).unwrap_err().contains("colour"));
+        let src = "[attributes.color]\nvalue = [\"red\"]\n[[categories]]\nname = \"mug\"\n";
+        assert!(Taxonomy::parse(src).is_err());
+        let src = "[attributes.size]\n[[categories]]\nname = \"mug\"\n";
+        assert!(Taxonomy::parse(src).unwrap_err().contains("values or units"));
+        let src = "[attributes.flavor]\nvalues = [\"mint\"]\nopen = [\"earl grey\"]\n[[categories]]\nname = \"tea\"\n";
+        assert!(Taxonomy::parse(src).unwrap_err().contains("not in values"));
+    }
+
+    #[test]
+    fn finds_measures() {
+        let tax = Taxonomy::builtin();
+        let cap = tax.attribute("capacity").unwrap();
+        assert_eq!(cap.measure_in("Insulated Bottle 750ml").as_deref(), Some("750 ml"));
+        assert_eq!(cap.measure_in("Tumbler (20 OZ)").as_deref(), Some("20 oz"));
+        assert_eq!(cap.measure_in("Serum, 1.7 fl oz.").as_deref(), Some("1.7 fl oz"));
+        assert_eq!(cap.measure_in("2 pack of lids"), None);
+        assert_eq!(cap.measure_in("model x100l"), None);
+    }
+}
@@ -0,0 +1,136 @@
+// Opt-in explanation of how an alt text was produced (`"debug": true`). Only callers holding
+// the admin key (CAPTIONER_ADMIN_KEY, sent as `x-admin-key`) may ask for it: the trace exposes
+// raw model output and internal heuristics.
+
+use axum::{
+    Json,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use captioner::ErrBody;
+use serde::Serialize;
+
+// Where refine_alt found a value.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    // Selected variant's options
+    Variant,
+    // Shop-supplied product tags and type
+    ProductTag,
+    ProductType,
+    Title,
+    // Model tags
+    Tag,
+    CaptionNearCategory,
+    Caption,
+}
+
+// Which way refine_alt went.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Branch {
+    // Model caption already named a product and a color with no people in it; kept as is.
+    CaptionKept,
+    // No category found; model caption returned with people words stripped.
+    NoCategory,
+    #[default]
+    Composed,
+    // Composition came out as a bare noun; a color/material from the title or tags was added.
+    ShortOutputGuard,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct AttributeChoice {
+    pub attribute: String,
+    pub value: String,
+    pub source: Source,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct RefineTrace {
+    pub branch: Branch,
+    pub category: Option<String>,
+    pub category_source: Option<Source>,
+    pub attributes: Vec<AttributeChoice>,
+}
+
+impl RefineTrace {
+    pub fn choose(&mut self, attribute: &str, value: &str, source: Source) {
+        self.attributes.push(AttributeChoice { attribute: attribute.to_string(), value: value.to_string(), source });
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct TagScore {
+    pub tag: String,
+    // Only remote endpoints that return `tag_scores` provide these.
+    pub score: Option<f32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub ms: f64,
+}
+
+// A text the rerank stage scored against the image.
+#[derive(Serialize, Clone, Debug)]
+pub struct RerankScore {
+    pub alt_text: String,
+    pub reason: String,
+    // CLIP cosine similarity to the image embedding
+    pub score: f32,
+    pub chosen: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DebugTrace {
+    pub model_caption: String,
+    pub cleaned_caption: String,
+    pub tags: Vec<TagScore>,
+    // None when the refine stage is disabled.
+    pub refine: Option<RefineTrace>,
+    // Present when the CLIP text encoder reranked the refined text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rerank: Option<Vec<RerankScore>>,
+    pub stages: Vec<StageTiming>,
+}
+
+pub struct Forbidden;
+
+impl IntoResponse for Forbidden {
+    fn into_response(self) -> Response {
+        let body = ErrBody { error: "debug requires a valid x-admin-key".into() };
+        (StatusCode::FORBIDDEN, Json(body)).into_response()
+    }
+}
+
+// Debug output is refused unless an admin key is configured and the request carries it.
+pub fn authorize(admin_key: Option<&str>, headers: &HeaderMap) -> Result<(), Forbidden> {
+    let given = headers.get("x-admin-key").and_then(|v| v.to_str().ok());
+    match (admin_key, given) {
+        (Some(key), Some(given)) if !key.is_empty() && constant_time_eq(key.as_bytes(), given.as_bytes()) => Ok(()),
+        _ => Err(Forbidden),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_matching_admin_key() {
+        let mut headers = HeaderMap::new();
+        assert!(authorize(Some("s3cret"), &headers).is_err());
+        headers.insert("x-admin-key", "s3cret".parse().unwrap());
+        assert!(authorize(Some("s3cret"), &headers).is_ok());
+        assert!(authorize(Some("other"), &headers).is_err());
+        assert!(authorize(None, &headers).is_err());
+        assert!(authorize(Some(""), &HeaderMap::new()).is_err());
+    }
+}
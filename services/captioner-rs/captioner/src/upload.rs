@@ -0,0 +1,213 @@
+// Request body handling for /v1/caption: JSON (optionally with a `data:` URI), multipart
+// uploads, and raw `image/*` bodies with metadata in the query string or headers.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    extract::{FromRequest, Multipart, Query, Request},
+    http::{HeaderMap, header::CONTENT_TYPE},
+};
+use base64::Engine as _;
+use bytes::Bytes;
+use captioner::ApiError;
+
+use crate::{CaptionReq, alt_policy};
+
+pub struct CaptionInput(pub CaptionReq);
+
+impl<S: Send + Sync> FromRequest<S> for CaptionInput {
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let ctype = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/json")
+            .to_ascii_lowercase();
+
+        let mut out = if ctype.starts_with("multipart/form-data") {
+            let mp = Multipart::from_request(req, state)
+                .await
+                .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid multipart body")))?;
+            from_multipart(mp).await?
+        } else if ctype.starts_with("image/") {
+            let (parts, body) = req.into_parts();
+            let Query(q) = Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+                .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid query string")))?;
+            let bytes = Bytes::from_request(Request::from_parts(parts.clone(), body), state)
+                .await
+                .map_err(|_| ApiError::BadRequest(Cow::Borrowed("read body failed")))?;
+            from_raw(bytes, &q, &parts.headers)
+        } else {
+            let Json(r) = Json::<CaptionReq>::from_request(req, state)
+                .await
+                .map_err(|e| ApiError::BadRequest(Cow::Owned(e.body_text())))?;
+            r
+        };
+        resolve_data_uri(&mut out)?;
+        Ok(CaptionInput(out))
+    }
+}
+
+// Fields: `image` (or `file`) holds the upload; any other text field is treated as request
+// metadata with the same names as the JSON body.
+async fn from_multipart(mut mp: Multipart) -> Result<CaptionReq, ApiError> {
+    let mut req = CaptionReq::default();
+    while let Some(field) = mp
+        .next_field()
+        .await
+        .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid multipart body")))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "image" || name == "file" {
+            let b = field
+                .bytes()
+                .await
+                .map_err(|_| ApiError::BadRequest(Cow::Borrowed("read upload failed")))?;
+            req.image_bytes = Some(b);
+        } else {
+            let v = field
+                .text()
+                .await
+                .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid multipart field")))?;
+            set_meta(&mut req, &name, v);
+        }
+    }
+    if req.image_bytes.as_ref().is_none_or(|b| b.is_empty()) && req.image_url.trim().is_empty() {
+        return Err(ApiError::BadRequest(Cow::Borrowed("image upload required")));
+    }
+    Ok(req)
+}
+
+// Metadata for raw bodies: query parameters win over `x-<name>` headers
+// (e.g. `?product_title=...` or `x-product-title: ...`).
+fn from_raw(bytes: Bytes, query: &HashMap<String, String>, headers: &HeaderMap) -> CaptionReq {
+    let mut req = CaptionReq { image_bytes: Some(bytes), ..Default::default() };
+    for (name, value) in headers {
+        if let (Some(key), Ok(v)) = (name.as_str().strip_prefix("x-"), value.to_str()) {
+            set_meta(&mut req, &key.replace('-', "_"), v.to_string());
+        }
+    }
+    for (k, v) in query {
+        set_meta(&mut req, k, v.clone());
+    }
+    req
+}
+
+fn set_meta(req: &mut CaptionReq, key: &str, value: String) {
+    match key {
+        "image_url" => req.image_url = value,
+        "product_title" | "title" => req.product_title = Some(value),
+        "shop" => req.shop = Some(value),
+        "alt_max_len" => req.alt_policy.get_or_insert_default().max_len = value.trim().parse().ok(),
+        "alt_people" => {
+            req.alt_policy.get_or_insert_default().people = match value.trim() {
+                "omit" => Some(alt_policy::People::Omit),
+                "neutral" => Some(alt_policy::People::Neutral),
+                "as_captioned" => Some(alt_policy::People::AsCaptioned),
+                _ => None,
+            }
+        }
+        "alt_speech" => req.alt_policy.get_or_insert_default().speech = Some(matches!(value.trim(), "true" | "1")),
+        "alt_limit" => {
+            req.alt_policy.get_or_insert_default().limit = match value.trim() {
+                "hard" => Some(alt_policy::Limit::Hard),
+                "soft" => Some(alt_policy::Limit::Soft),
+                _ => None,
+            }
+        }
+        "vendor" => req.vendor = Some(value),
+        "product_type" => req.product_type = Some(value),
+        // Shopify's comma-separated tag string
+        "tags" => req.tags.extend(value.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string)),
+        "color" => req.variant.color = Some(value),
+        "size" => req.variant.size = Some(value),
+        "material" => req.variant.material = Some(value),
+        "candidates" => req.candidates = value.trim().parse().ok(),
+        "locale" => req.locale = Some(value),
+        "locales" => req.locales = value.split(',').map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect(),
+        "resource_id" => req.resource_id = Some(value),
+        "translatable_content_digest" => req.translatable_content_digest = Some(value),
+        "existing_alt" => req.existing_alt = Some(value),
+        "long_description" => req.long_description = matches!(value.trim(), "true" | "1"),
+        "include_brand" => req.include_brand = matches!(value.trim(), "true" | "1"),
+        "debug" => req.debug = matches!(value.trim(), "true" | "1"),
+        _ => {}
+    }
+}
+
+// `image_url` may carry the image inline as `data:image/<type>;base64,<payload>`.
+pub fn resolve_data_uri(req: &mut CaptionReq) -> Result<(), ApiError> {
+    if req.image_bytes.is_some() || !req.image_url.starts_with("data:") {
+        return Ok(());
+    }
+    let bytes = decode_data_uri(&req.image_url)?;
+    req.image_bytes = Some(bytes);
+    req.image_url.clear();
+    Ok(())
+}
+
+fn decode_data_uri(uri: &str) -> Result<Bytes, ApiError> {
+    let rest = &uri["data:".len()..];
+    let (meta, payload) = rest
+        .split_once(',')
+        .ok_or(ApiError::BadRequest(Cow::Borrowed("invalid data uri")))?;
+    let mut params = meta.split(';');
+    let mime = params.next().unwrap_or_default().to_ascii_lowercase();
+    if !mime.starts_with("image/") {
+        return Err(ApiError::BadRequest(Cow::Borrowed("data uri must be an image")));
+    }
+    if !params.any(|p| p.eq_ignore_ascii_case("base64")) {
+        return Err(ApiError::BadRequest(Cow::Borrowed("data uri must be base64")));
+    }
+    let payload: String = payload.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(payload.as_bytes())
+        .map(Bytes::from)
+        .map_err(|_| ApiError::BadRequest(Cow::Borrowed("invalid base64 in data uri")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_image_data_uris() {
+        let mut req = CaptionReq { image_url: "data:image/png;base64,iVBO\nRw0K".into(), ..Default::default() };
+        resolve_data_uri(&mut req).unwrap();
+        assert_eq!(req.image_bytes.as_deref(), Some(&b"\x89PNG\r\n"[..]));
+        assert!(req.image_url.is_empty());
+    }
+
+    #[test]
+    fn rejects_non_image_or_plain_data_uris() {
+        for uri in ["data:text/plain;base64,aGk=", "data:image/svg+xml,<svg/>", "data:image/png;base64,@@@", "data:image/png"] {
+            let mut req = CaptionReq { image_url: uri.into(), ..Default::default() };
+            assert!(matches!(resolve_data_uri(&mut req), Err(ApiError::BadRequest(_))), "{uri}");
+        }
+    }
+
+    #[test]
+    fn raw_body_metadata_from_query_and_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-product-title", "Header title".parse().unwrap());
+        let req = from_raw(Bytes::from_static(b"img"), &HashMap::new(), &headers);
+        assert_eq!(req.product_title.as_deref(), Some("Header title"));
+
+        let q = HashMap::from([("product_title".to_string(), "Query title".to_string())]);
+        let req = from_raw(Bytes::from_static(b"img"), &q, &headers);
+        assert_eq!(req.product_title.as_deref(), Some("Query title"));
+
+        let q = HashMap::from([("alt_max_len".to_string(), "80".to_string()), ("alt_limit".to_string(), "hard".to_string())]);
+        let p = from_raw(Bytes::from_static(b"img"), &q, &headers).alt_policy.unwrap();
+        assert_eq!((p.max_len, p.limit), (Some(80), Some(alt_policy::Limit::Hard)));
+
+        let q = HashMap::from([("tags".to_string(), "wool, Winter,,".to_string()), ("color".to_string(), "Navy".to_string())]);
+        let req = from_raw(Bytes::from_static(b"img"), &q, &HeaderMap::new());
+        assert_eq!(req.tags, ["wool", "Winter"]);
+        assert_eq!(req.variant.color.as_deref(), Some("Navy"));
+    }
+}
@@ -0,0 +1,275 @@
+// Outbound policy for user-supplied image URLs (SSRF guard).
+//
+// Checks are applied three times: to the requested URL, to every redirect hop, and to
+// every address a hostname resolves to (via `PolicyResolver`), so a public name that
+// resolves or redirects into our network is refused before a connection is made.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::Url;
+
+pub const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyViolation {
+    #[error("scheme not allowed")]
+    Scheme,
+    #[error("host not allowed: {0}")]
+    Host(String),
+    #[error("address not allowed: {0}")]
+    Address(IpAddr),
+    #[error("too many redirects")]
+    TooManyRedirects,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct UrlPolicy {
+    // Hosts allowed as image sources. Empty means any public host. An entry starting
+    // with "." matches the domain and all of its subdomains (".shopify.com").
+    pub allow_hosts: Vec<String>,
+    // Permit loopback targets; only meant for local development and tests.
+    pub allow_loopback: bool,
+}
+
+impl UrlPolicy {
+    pub fn from_env() -> Self {
+        let allow_hosts = std::env::var("CAPTIONER_FETCH_ALLOW_HOSTS")
+            .ok()
+            .map(|s| {
+                s.split(',')
+                    .map(|h| h.trim().to_ascii_lowercase())
+                    .filter(|h| !h.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let allow_loopback = matches!(
+            std::env::var("CAPTIONER_FETCH_ALLOW_LOOPBACK").ok().as_deref(),
+            Some("1") | Some("true")
+        );
+        UrlPolicy { allow_hosts, allow_loopback }
+    }
+
+    pub fn check_url(&self, url: &Url) -> Result<(), PolicyViolation> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(PolicyViolation::Scheme);
+        }
+        let host = url.host_str().ok_or_else(|| PolicyViolation::Host(String::new()))?;
+        // IP literals never reach the resolver, so check them here.
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            return self.check_literal(ip);
+        }
+        if !self.host_allowed(host) {
+            return Err(PolicyViolation::Host(host.to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_literal(&self, ip: IpAddr) -> Result<(), PolicyViolation> {
+        if !self.allow_hosts.is_empty() && !self.host_allowed(&ip.to_string()) {
+            return Err(PolicyViolation::Host(ip.to_string()));
+        }
+        if !self.ip_allowed(ip) {
+            return Err(PolicyViolation::Address(ip));
+        }
+        Ok(())
+    }
+
+    pub fn host_allowed(&self, host: &str) -> bool {
+        if self.allow_hosts.is_empty() {
+            return true;
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.allow_hosts.iter().any(|a| match a.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(a.as_str()),
+            None => host == *a,
+        })
+    }
+
+    pub fn ip_allowed(&self, ip: IpAddr) -> bool {
+        if ip.is_loopback() || ip.to_canonical().is_loopback() {
+            return self.allow_loopback;
+        }
+        match ip.to_canonical() {
+            IpAddr::V4(v4) => v4_public(v4),
+            IpAddr::V6(v6) => v6_public(v6),
+        }
+    }
+}
+
+fn v4_public(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_private()
+        || ip.is_link_local() // 169.254/16, includes cloud metadata 169.254.169.254
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)) // carrier-grade NAT 100.64/10
+        || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+        || (a == 198 && (18..20).contains(&b)) // benchmarking 198.18/15
+        || a >= 240) // reserved
+}
+
+fn v6_public(ip: Ipv6Addr) -> bool {
+    let seg = ip.segments();
+    // Loopback is only allowed as itself, not tunnelled
+    let embedded = embedded_v4(ip);
+    if !embedded.is_empty() {
+        return embedded.iter().all(|v4| !v4.is_loopback() && v4_public(*v4));
+    }
+    !(ip.is_unspecified()
+        || ip.is_multicast()
+        || (seg[0] & 0xfe00) == 0xfc00 // unique local fc00::/7 (includes fd00:ec2::254)
+        || (seg[0] & 0xffc0) == 0xfe80 // link-local fe80::/10
+        || (seg[0] & 0xffc0) == 0xfec0 // deprecated site-local fec0::/10
+        || (seg[0] == 0x2001 && seg[1] == 0x0db8)) // documentation
+}
+
+// IPv4 addresses a v6 address routes to, for the ranges that embed them.
+fn embedded_v4(ip: Ipv6Addr) -> Vec<Ipv4Addr> {
+    let v4 = |hi: u16, lo: u16| {
+        let [a, b] = hi.to_be_bytes();
+        let [c, d] = lo.to_be_bytes();
+        Ipv4Addr::new(a, b, c, d)
+    };
+    match ip.segments() {
+        // NAT64 well-known prefix 64:ff9b::/96
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => vec![v4(hi, lo)],
+        // 6to4 2002::/16: the relay's address
+        [0x2002, hi, lo, ..] => vec![v4(hi, lo)],
+        // Teredo 2001::/32: the server's address and the client's, stored inverted
+        [0x2001, 0, s_hi, s_lo, _, _, c_hi, c_lo] => vec![v4(s_hi, s_lo), v4(!c_hi, !c_lo)],
+        // Deprecated IPv4-compatible ::a.b.c.d (not :: or ::1)
+        [0, 0, 0, 0, 0, 0, hi, lo] if u32::from(v4(hi, lo)) > 1 => vec![v4(hi, lo)],
+        _ => Vec::new(),
+    }
+}
+
+// DNS resolver that drops any address the policy rejects. Connecting only to the
+// filtered addresses also closes the gap where a name is re-resolved between check and
+// connect (DNS rebinding).
+pub struct PolicyResolver {
+    policy: Arc<UrlPolicy>,
+}
+
+impl PolicyResolver {
+    pub fn new(policy: Arc<UrlPolicy>) -> Self {
+        PolicyResolver { policy }
+    }
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let mut blocked = None;
+            let allowed: Vec<SocketAddr> = resolved
+                .into_iter()
+                .filter(|a| {
+                    let ok = policy.ip_allowed(a.ip());
+                    if !ok {
+                        blocked = Some(a.ip());
+                    }
+                    ok
+                })
+                .collect();
+            if let (true, Some(ip)) = (allowed.is_empty(), blocked) {
+                tracing::warn!(host = %host, addr = %ip, "image host resolves to a blocked address");
+                return Err(Box::new(PolicyViolation::Address(ip)) as Box<dyn std::error::Error + Send + Sync>);
+            }
+            Ok(Box::new(allowed.into_iter()) as Addrs)
+        })
+    }
+}
+
+pub fn redirect_policy(policy: Arc<UrlPolicy>) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error(PolicyViolation::TooManyRedirects);
+        }
+        match policy.check_url(attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(v) => {
+                tracing::warn!(url = %attempt.url(), err = %v, "redirect blocked by url policy");
+                attempt.error(v)
+            }
+        }
+    })
+}
+
+// Walk an error's source chain looking for a policy violation raised by the resolver or
+// the redirect policy.
+pub fn find_violation<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a PolicyViolation> {
+    let mut cur: Option<&'a (dyn std::error::Error + 'static)> = Some(err);
+    while let Some(e) = cur {
+        if let Some(v) = e.downcast_ref::<PolicyViolation>() {
+            return Some(v);
+        }
+        cur = e.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn blocks_internal_ranges() {
+        let p = UrlPolicy::default();
+        for s in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1",
+            "0.0.0.0", "::1", "fd00:ec2::254", "fe80::1", "::ffff:10.0.0.1", "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe", "64:ff9b::7f00:1",
+            // 6to4 for 10.0.0.1 and 169.254.169.254
+            "2002:a00:1::1", "2002:a9fe:a9fe::",
+            // Teredo with a private server, then with a private client (10.0.0.1 inverted)
+            "2001:0:a00:1::", "2001:0:4136:e378:8000:63bf:f5ff:fffe",
+            // IPv4-compatible
+            "::a00:1", "::7f00:1", "::a9fe:a9fe",
+        ] {
+            assert!(!p.ip_allowed(ip(s)), "{s} should be blocked");
+        }
+        for s in ["23.227.38.65", "151.101.1.1", "2606:4700::6810:84e5", "2002:17e3:2641::1", "2001:0:4136:e378:8000:63bf:e8fc:d9be"] {
+            assert!(p.ip_allowed(ip(s)), "{s} should be allowed");
+        }
+    }
+
+    #[test]
+    fn loopback_opt_in_only_affects_loopback() {
+        let p = UrlPolicy { allow_loopback: true, ..Default::default() };
+        assert!(p.ip_allowed(ip("127.0.0.1")));
+        assert!(p.ip_allowed(ip("::1")));
+        assert!(!p.ip_allowed(ip("169.254.169.254")));
+        assert!(!p.ip_allowed(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn allowlist_matches_exact_and_suffix() {
+        let p = UrlPolicy { allow_hosts: vec!["cdn.shopify.com".into(), ".example-cdn.net".into()], ..Default::default() };
+        assert!(p.host_allowed("cdn.shopify.com"));
+        assert!(p.host_allowed("CDN.Shopify.com."));
+        assert!(p.host_allowed("img.example-cdn.net"));
+        assert!(p.host_allowed("example-cdn.net"));
+        assert!(!p.host_allowed("evil-cdn.shopify.com.attacker.io"));
+        assert!(!p.host_allowed("notexample-cdn.net"));
+    }
+
+    #[test]
+    fn check_url_rejects_schemes_and_literals() {
+        let p = UrlPolicy::default();
+        assert!(matches!(p.check_url(&Url::parse("ftp://cdn.shopify.com/x.jpg").unwrap()), Err(PolicyViolation::Scheme)));
+        assert!(matches!(p.check_url(&Url::parse("http://169.254.169.254/latest/meta-data/").unwrap()), Err(PolicyViolation::Address(_))));
+        assert!(matches!(p.check_url(&Url::parse("http://[::1]:8080/").unwrap()), Err(PolicyViolation::Address(_))));
+        assert!(p.check_url(&Url::parse("https://cdn.shopify.com/s/files/x.jpg").unwrap()).is_ok());
+    }
+}
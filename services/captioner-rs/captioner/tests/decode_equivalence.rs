@@ -3,6 +3,7 @@ fn decode_equivalence_dimensions() {
     let bytes = include_bytes!("fixtures/sample.jpg");
 
     let img_image = image::load_from_memory(bytes).expect("valid jpeg");
+    assert!(img_image.width() > 0 && img_image.height() > 0);
 
     #[cfg(feature = "turbo")]
     {
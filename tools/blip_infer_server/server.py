@@ -1,4 +1,6 @@
 #!/usr/bin/env python
+import base64
+import binascii
 import io
 import os
 from typing import Optional
@@ -22,8 +24,13 @@ model = BlipForConditionalGeneration.from_pretrained(MODEL_DIR).to(DEVICE).eval(
 
 
 class InferReq(BaseModel):
-    image_url: str
+    # The image bytes, base64-encoded; the Rust captioner always sends these
+    image_b64: Optional[str] = None
+    # Fetched by this server when image_b64 is absent
+    image_url: Optional[str] = None
     title: Optional[str] = None
+    # Beam-search alternatives to return as `captions` (best first)
+    n_best: int = 1
 
 
 def fetch_image(url: str) -> Image.Image:
@@ -33,25 +40,52 @@ def fetch_image(url: str) -> Image.Image:
     return Image.open(io.BytesIO(r.content)).convert("RGB")
 
 
+def decode_image(b64: str) -> Image.Image:
+    try:
+        return Image.open(io.BytesIO(base64.b64decode(b64, validate=True))).convert("RGB")
+    except (binascii.Error, ValueError, OSError):
+        raise HTTPException(400, detail="image_b64 is not a base64-encoded image")
+
+
+def clean(caption: str) -> str:
+    # Basic cleanup per Shopify guidance
+    for p in ["a product photo of ", "a studio product photo of ", "a studio product shot of ", "a product image of ", "a photo of ", "an image of ", "a picture of "]:
+        if caption.lower().startswith(p):
+            caption = caption[len(p):]
+            break
+    return caption.strip()
+
+
 @app.post("/v1/infer")
 def infer(req: InferReq):
-    if not (req.image_url.startswith("http://") or req.image_url.startswith("https://")):
-        raise HTTPException(400, detail="image_url must be http(s)")
-    image = fetch_image(req.image_url)
+    if req.image_b64:
+        image = decode_image(req.image_b64)
+    elif req.image_url:
+        if not (req.image_url.startswith("http://") or req.image_url.startswith("https://")):
+            raise HTTPException(400, detail="image_url must be http(s)")
+        image = fetch_image(req.image_url)
+    else:
+        raise HTTPException(400, detail="image_b64 or image_url required")
     # Prepend title lightly if provided (acts as a steer)
     prompt = req.title.strip() if req.title else None
     inputs = processor(images=image, text=prompt, return_tensors="pt").to(DEVICE)
+    n = max(1, min(req.n_best, 10))
     with torch.no_grad():
-        out = model.generate(**inputs, max_new_tokens=16)
-    caption = processor.decode(out[0], skip_special_tokens=True)
-    # Basic cleanup per Shopify guidance
-    for p in ["a product photo of ", "a studio product photo of ", "a studio product shot of ", "a product image of ", "a photo of ", "an image of ", "a picture of "]:
-        if caption.lower().startswith(p):
-            caption = caption[len(p):]
-            break
-    caption = caption.strip()
+        if n == 1:
+            out = model.generate(**inputs, max_new_tokens=16)
+            sequences, scores = out, None
+        else:
+            out = model.generate(
+                **inputs, max_new_tokens=16, num_beams=max(n, 3), num_return_sequences=n,
+                output_scores=True, return_dict_in_generate=True,
+            )
+            sequences, scores = out.sequences, out.sequences_scores.exp().tolist()
+    captions = [clean(processor.decode(seq, skip_special_tokens=True)) for seq in sequences]
     # tags left empty by default in this server; Rust refiner can add product-centric details
-    return {"caption": caption, "tags": []}
+    resp = {"caption": captions[0], "tags": []}
+    if scores is not None:
+        resp["captions"] = [{"caption": c, "score": s} for c, s in zip(captions, scores)]
+    return resp
 
 
 if __name__ == "__main__":